@@ -1,7 +1,7 @@
 use async_std::{net::TcpStream, sync::RwLock};
-use std::{net::SocketAddr, sync::Arc};
+use std::{net::SocketAddr, path::PathBuf, sync::Arc};
 
-use colmeia_hyperstack::{hyperdrive, utils::PublicKeyExt};
+use colmeia_hyperstack::{hypercore::FeedOptions, hyperdrive, utils::PublicKeyExt};
 
 fn name() -> String {
     let args: Vec<String> = std::env::args().skip(1).collect();
@@ -16,20 +16,18 @@ fn address() -> SocketAddr {
     input.parse().expect("invalid ip:port as input")
 }
 
-// TODO: send to a folder
-// use std::path::PathBuf;
-// fn folder() -> PathBuf {
-//     let args: Vec<String> = std::env::args().skip(3).collect();
-//     args.first().expect("must have folder as argument").into()
-// }
+fn folder() -> PathBuf {
+    let args: Vec<String> = std::env::args().skip(3).collect();
+    args.first().expect("must have folder as argument").into()
+}
 
 fn main() {
     env_logger::init();
 
     let key = name();
     let address = address();
+    let folder = folder();
 
-    // let path = name();
     async_std::task::block_on(async {
         let tcp_stream = TcpStream::connect(address)
             .await
@@ -38,10 +36,37 @@ fn main() {
 
         let client = hypercore_protocol::ProtocolBuilder::initiator().connect(tcp_stream);
 
-        let hyperdrive = hyperdrive::in_memmory(hash)
+        // Keep the feeds with the files, like `dat clone`, so running again only downloads what changed
+        let hyperdrive = hyperdrive::in_disk(hash, folder.join(".colmeia"))
             .await
             .expect("Invalid intialization");
         let hyperdrive = Arc::new(RwLock::new(hyperdrive));
-        hyperdrive::replicate_hyperdrive(client, hyperdrive).await;
+        // Only download, so replication ends once the drive is cloned
+        let options = FeedOptions {
+            uploading: false,
+            ..FeedOptions::default()
+        };
+        if let Err(error) =
+            hyperdrive::replicate_hyperdrive_with(client, hyperdrive.clone(), options).await
+        {
+            log::error!("replication failed: {:?}", error);
+        }
+
+        // Exporting a partial clone would wait on blocks nobody is sending anymore
+        let hyperdrive = hyperdrive.read().await;
+        match hyperdrive.is_downloaded().await {
+            Ok(true) => {}
+            Ok(false) => {
+                log::error!("the drive was only partially cloned, run again to download the rest");
+                std::process::exit(1);
+            }
+            Err(error) => {
+                log::error!("could not check the drive was cloned: {:?}", error);
+                std::process::exit(1);
+            }
+        }
+        if let Err(error) = hyperdrive.export(&folder).await {
+            log::error!("failed to export to {}: {:?}", folder.display(), error);
+        }
     });
 }
@@ -0,0 +1,679 @@
+use async_std::{fs, sync::RwLock, task};
+use colmeia_hyperstack::{
+    hypercore::FeedOptions,
+    hyperdrive::{Hyperdrive, Stat, StatExt},
+    utils::PublicKeyExt,
+    Hyperstack,
+};
+use fuse::{
+    FileAttr, FileType, Filesystem, ReplyAttr, ReplyCreate, ReplyData, ReplyDirectory, ReplyEmpty,
+    ReplyEntry, ReplyOpen, ReplyWrite, Request,
+};
+use futures::{AsyncRead, AsyncReadExt, StreamExt, TryStreamExt};
+use libc::c_int;
+use std::{
+    collections::HashMap,
+    ffi::OsStr,
+    path::{Path, PathBuf},
+    sync::Arc,
+};
+use time::Timespec;
+
+type Drive = Arc<RwLock<Hyperdrive<random_access_disk::RandomAccessDisk>>>;
+
+// How long the kernel may cache entries and attributes
+const TTL: Timespec = Timespec { sec: 1, nsec: 0 };
+const ROOT: u64 = 1;
+
+fn name() -> String {
+    let args: Vec<String> = std::env::args().skip(1).collect();
+    args.first().expect("must have dat name as argument").into()
+}
+
+fn mountpoint() -> PathBuf {
+    let args: Vec<String> = std::env::args().skip(2).collect();
+    args.first()
+        .expect("must have mountpoint as argument")
+        .into()
+}
+
+fn folder() -> PathBuf {
+    let args: Vec<String> = std::env::args().skip(3).collect();
+    args.first()
+        .map(PathBuf::from)
+        .unwrap_or_else(|| PathBuf::from(".colmeia"))
+}
+
+/// The keys saved by `colmeia-share`, if the drive is ours
+async fn keypair(storage: &Path, public: &hypercore::PublicKey) -> Option<ed25519_dalek::Keypair> {
+    let encoded = fs::read_to_string(storage.join("secret_key")).await.ok()?;
+    let bytes = hex::decode(encoded.trim()).ok()?;
+    let secret = hypercore::SecretKey::from_bytes(&bytes).ok()?;
+    if hypercore::PublicKey::from(&secret) != *public {
+        return None;
+    }
+    Some(ed25519_dalek::Keypair {
+        secret,
+        public: *public,
+    })
+}
+
+fn timespec(millis: u64) -> Timespec {
+    Timespec::new((millis / 1000) as i64, ((millis % 1000) * 1_000_000) as i32)
+}
+
+fn kind(stat: &Stat) -> FileType {
+    if stat.is_dir() {
+        FileType::Directory
+    } else if stat.is_symlink() {
+        FileType::Symlink
+    } else {
+        FileType::RegularFile
+    }
+}
+
+/// Writes the file keeping the permissions it has on the drive
+async fn write_file(
+    hyperdrive: &Hyperdrive<random_access_disk::RandomAccessDisk>,
+    path: &str,
+    data: &[u8],
+) -> anyhow::Result<()> {
+    let mode = hyperdrive
+        .stat(path)
+        .await?
+        .map(|stat| stat.get_mode())
+        .unwrap_or(0o644);
+    hyperdrive.write_file_with_mode(path, data, mode).await
+}
+
+fn errno(error: anyhow::Error) -> c_int {
+    log::warn!("drive operation failed: {:?}", error);
+    libc::EIO
+}
+
+type Reader = Box<dyn AsyncRead + Unpin>;
+
+struct Handle {
+    path: String,
+    // Files open for writing are kept in memory and written to the drive when flushed
+    data: Option<Vec<u8>>,
+    dirty: bool,
+    // The stream of the last read and where it stopped, sequential reads continue from it
+    reader: Option<(u64, Reader)>,
+}
+
+struct DriveFs {
+    hyperdrive: Drive,
+    writable: bool,
+    paths: HashMap<u64, String>,
+    inodes: HashMap<String, u64>,
+    handles: HashMap<u64, Handle>,
+    next_handle: u64,
+}
+
+impl DriveFs {
+    fn new(hyperdrive: Drive, writable: bool) -> Self {
+        let mut fs = Self {
+            hyperdrive,
+            writable,
+            paths: HashMap::new(),
+            inodes: HashMap::new(),
+            handles: HashMap::new(),
+            next_handle: 1,
+        };
+        fs.paths.insert(ROOT, String::new());
+        fs.inodes.insert(String::new(), ROOT);
+        fs
+    }
+
+    fn inode(&mut self, path: &str) -> u64 {
+        if let Some(ino) = self.inodes.get(path) {
+            return *ino;
+        }
+        let ino = self.paths.len() as u64 + 1;
+        self.paths.insert(ino, path.to_string());
+        self.inodes.insert(path.to_string(), ino);
+        ino
+    }
+
+    fn path(&self, ino: u64) -> Option<String> {
+        self.paths.get(&ino).cloned()
+    }
+
+    fn child(&self, parent: u64, name: &OsStr) -> Option<String> {
+        let parent = self.paths.get(&parent)?;
+        let name = name.to_str()?;
+        if parent.is_empty() {
+            Some(name.to_string())
+        } else {
+            Some(format!("{}/{}", parent, name))
+        }
+    }
+
+    fn stat(&self, path: &str) -> anyhow::Result<Option<Stat>> {
+        task::block_on(async { self.hyperdrive.read().await.stat(path).await })
+    }
+
+    fn attr(&mut self, path: &str, stat: &Stat) -> FileAttr {
+        // Writes not flushed yet are not on the drive
+        let buffered = self
+            .handles
+            .values()
+            .find(|handle| handle.path == path && handle.dirty)
+            .and_then(|handle| handle.data.as_ref())
+            .map(|data| data.len() as u64);
+        let size = buffered.unwrap_or_else(|| stat.get_size());
+        let kind = kind(stat);
+        FileAttr {
+            ino: self.inode(path),
+            size,
+            blocks: (size + 511) / 512,
+            atime: timespec(stat.get_mtime()),
+            mtime: timespec(stat.get_mtime()),
+            ctime: timespec(stat.get_ctime()),
+            crtime: timespec(stat.get_ctime()),
+            kind,
+            perm: (stat.get_mode() & 0o7777) as u16,
+            nlink: if kind == FileType::Directory { 2 } else { 1 },
+            uid: stat.get_uid(),
+            gid: stat.get_gid(),
+            rdev: 0,
+            flags: 0,
+        }
+    }
+
+    fn entry(&mut self, path: &str) -> Result<FileAttr, c_int> {
+        match self.stat(path) {
+            Ok(Some(stat)) => Ok(self.attr(path, &stat)),
+            Ok(None) => Err(libc::ENOENT),
+            Err(error) => Err(errno(error)),
+        }
+    }
+
+    fn flush_handle(&mut self, fh: u64) -> Result<(), c_int> {
+        let handle = match self.handles.get_mut(&fh) {
+            Some(handle) => handle,
+            None => return Err(libc::EBADF),
+        };
+        if let (true, Some(data)) = (handle.dirty, &handle.data) {
+            let hyperdrive = self.hyperdrive.clone();
+            task::block_on(async {
+                write_file(&*hyperdrive.read().await, &handle.path, data).await
+            })
+            .map_err(errno)?;
+            handle.dirty = false;
+        }
+        Ok(())
+    }
+
+    fn ensure_writable(&self) -> Result<(), c_int> {
+        if self.writable {
+            Ok(())
+        } else {
+            Err(libc::EROFS)
+        }
+    }
+}
+
+impl Filesystem for DriveFs {
+    fn lookup(&mut self, _req: &Request, parent: u64, name: &OsStr, reply: ReplyEntry) {
+        let path = match self.child(parent, name) {
+            Some(path) => path,
+            None => return reply.error(libc::ENOENT),
+        };
+        match self.entry(&path) {
+            Ok(attr) => reply.entry(&TTL, &attr, 0),
+            Err(error) => reply.error(error),
+        }
+    }
+
+    fn getattr(&mut self, _req: &Request, ino: u64, reply: ReplyAttr) {
+        let path = match self.path(ino) {
+            Some(path) => path,
+            None => return reply.error(libc::ENOENT),
+        };
+        match self.entry(&path) {
+            Ok(attr) => reply.attr(&TTL, &attr),
+            Err(error) => reply.error(error),
+        }
+    }
+
+    fn setattr(
+        &mut self,
+        _req: &Request,
+        ino: u64,
+        _mode: Option<u32>,
+        _uid: Option<u32>,
+        _gid: Option<u32>,
+        size: Option<u64>,
+        _atime: Option<Timespec>,
+        _mtime: Option<Timespec>,
+        fh: Option<u64>,
+        _crtime: Option<Timespec>,
+        _chgtime: Option<Timespec>,
+        _bkuptime: Option<Timespec>,
+        _flags: Option<u32>,
+        reply: ReplyAttr,
+    ) {
+        let path = match self.path(ino) {
+            Some(path) => path,
+            None => return reply.error(libc::ENOENT),
+        };
+        // Only truncating is supported, other attributes come from the drive
+        if let Some(size) = size {
+            if let Err(error) = self.ensure_writable() {
+                return reply.error(error);
+            }
+            let buffer = fh
+                .and_then(|fh| self.handles.get_mut(&fh))
+                .and_then(|handle| {
+                    handle.dirty = true;
+                    handle.data.as_mut()
+                });
+            match buffer {
+                Some(data) => data.resize(size as usize, 0),
+                None => {
+                    let hyperdrive = self.hyperdrive.clone();
+                    let truncated = task::block_on(async {
+                        let hyperdrive = hyperdrive.read().await;
+                        let mut data = hyperdrive.read_file(&path).await?;
+                        data.resize(size as usize, 0);
+                        write_file(&hyperdrive, &path, &data).await
+                    });
+                    if let Err(error) = truncated {
+                        return reply.error(errno(error));
+                    }
+                }
+            }
+        }
+        match self.entry(&path) {
+            Ok(attr) => reply.attr(&TTL, &attr),
+            Err(error) => reply.error(error),
+        }
+    }
+
+    fn readlink(&mut self, _req: &Request, ino: u64, reply: ReplyData) {
+        let path = match self.path(ino) {
+            Some(path) => path,
+            None => return reply.error(libc::ENOENT),
+        };
+        match self.stat(&path) {
+            Ok(Some(stat)) if stat.is_symlink() => reply.data(stat.get_linkname().as_bytes()),
+            Ok(Some(_)) => reply.error(libc::EINVAL),
+            Ok(None) => reply.error(libc::ENOENT),
+            Err(error) => reply.error(errno(error)),
+        }
+    }
+
+    fn mkdir(&mut self, _req: &Request, parent: u64, name: &OsStr, mode: u32, reply: ReplyEntry) {
+        if let Err(error) = self.ensure_writable() {
+            return reply.error(error);
+        }
+        let path = match self.child(parent, name) {
+            Some(path) => path,
+            None => return reply.error(libc::ENOENT),
+        };
+        let hyperdrive = self.hyperdrive.clone();
+        if let Err(error) =
+            task::block_on(async { hyperdrive.read().await.mkdir_with_mode(&path, mode).await })
+        {
+            return reply.error(errno(error));
+        }
+        match self.entry(&path) {
+            Ok(attr) => reply.entry(&TTL, &attr, 0),
+            Err(error) => reply.error(error),
+        }
+    }
+
+    fn unlink(&mut self, _req: &Request, parent: u64, name: &OsStr, reply: ReplyEmpty) {
+        if let Err(error) = self.ensure_writable() {
+            return reply.error(error);
+        }
+        let path = match self.child(parent, name) {
+            Some(path) => path,
+            None => return reply.error(libc::ENOENT),
+        };
+        let hyperdrive = self.hyperdrive.clone();
+        match task::block_on(async { hyperdrive.read().await.unlink(&path).await }) {
+            Ok(()) => reply.ok(),
+            Err(error) => reply.error(errno(error)),
+        }
+    }
+
+    fn rmdir(&mut self, _req: &Request, parent: u64, name: &OsStr, reply: ReplyEmpty) {
+        if let Err(error) = self.ensure_writable() {
+            return reply.error(error);
+        }
+        let path = match self.child(parent, name) {
+            Some(path) => path,
+            None => return reply.error(libc::ENOENT),
+        };
+        let hyperdrive = self.hyperdrive.clone();
+        let removed = task::block_on(async {
+            let hyperdrive = hyperdrive.read().await;
+            if !hyperdrive.readdir(&path).await?.is_empty() {
+                return Ok(false);
+            }
+            hyperdrive.unlink(&path).await?;
+            Ok(true)
+        });
+        match removed {
+            Ok(true) => reply.ok(),
+            Ok(false) => reply.error(libc::ENOTEMPTY),
+            Err(error) => reply.error(errno(error)),
+        }
+    }
+
+    fn symlink(
+        &mut self,
+        _req: &Request,
+        parent: u64,
+        name: &OsStr,
+        link: &Path,
+        reply: ReplyEntry,
+    ) {
+        if let Err(error) = self.ensure_writable() {
+            return reply.error(error);
+        }
+        let (path, target) = match (self.child(parent, name), link.to_str()) {
+            (Some(path), Some(target)) => (path, target),
+            _ => return reply.error(libc::EINVAL),
+        };
+        let hyperdrive = self.hyperdrive.clone();
+        if let Err(error) =
+            task::block_on(async { hyperdrive.read().await.symlink(target, &path).await })
+        {
+            return reply.error(errno(error));
+        }
+        match self.entry(&path) {
+            Ok(attr) => reply.entry(&TTL, &attr, 0),
+            Err(error) => reply.error(error),
+        }
+    }
+
+    fn open(&mut self, _req: &Request, ino: u64, flags: u32, reply: ReplyOpen) {
+        let path = match self.path(ino) {
+            Some(path) => path,
+            None => return reply.error(libc::ENOENT),
+        };
+        let flags = flags as c_int;
+        let data = if flags & libc::O_ACCMODE == libc::O_RDONLY {
+            None
+        } else {
+            if let Err(error) = self.ensure_writable() {
+                return reply.error(error);
+            }
+            if flags & libc::O_TRUNC != 0 {
+                Some(vec![])
+            } else {
+                let hyperdrive = self.hyperdrive.clone();
+                match task::block_on(async { hyperdrive.read().await.read_file(&path).await }) {
+                    Ok(data) => Some(data),
+                    Err(error) => return reply.error(errno(error)),
+                }
+            }
+        };
+
+        let fh = self.next_handle;
+        self.next_handle += 1;
+        self.handles.insert(
+            fh,
+            Handle {
+                path,
+                dirty: flags & libc::O_TRUNC != 0 && data.is_some(),
+                data,
+                reader: None,
+            },
+        );
+        reply.opened(fh, 0);
+    }
+
+    fn read(
+        &mut self,
+        _req: &Request,
+        ino: u64,
+        fh: u64,
+        offset: i64,
+        size: u32,
+        reply: ReplyData,
+    ) {
+        let start = offset as u64;
+        let end = start.saturating_add(u64::from(size));
+        if let Some(data) = self
+            .handles
+            .get(&fh)
+            .and_then(|handle| handle.data.as_ref())
+        {
+            let start = (start as usize).min(data.len());
+            let end = (end as usize).min(data.len());
+            return reply.data(&data[start..end]);
+        }
+
+        let path = match self.path(ino) {
+            Some(path) => path,
+            None => return reply.error(libc::ENOENT),
+        };
+        let cached = self
+            .handles
+            .get_mut(&fh)
+            .and_then(|handle| handle.reader.take());
+        let hyperdrive = self.hyperdrive.clone();
+        let read = task::block_on(async {
+            let mut reader = match cached {
+                Some((position, reader)) if position == start => reader,
+                _ => {
+                    // The blocks before the offset are skipped by seeking with the merkle tree
+                    let stream = hyperdrive
+                        .read()
+                        .await
+                        .create_read_stream(&path, start..)
+                        .await?;
+                    Box::new(Box::pin(stream).into_async_read()) as Reader
+                }
+            };
+            let mut data = vec![];
+            (&mut reader)
+                .take(u64::from(size))
+                .read_to_end(&mut data)
+                .await?;
+            anyhow::Result::<_>::Ok((data, reader))
+        });
+        match read {
+            Ok((data, reader)) => {
+                if let Some(handle) = self.handles.get_mut(&fh) {
+                    handle.reader = Some((start + data.len() as u64, reader));
+                }
+                reply.data(&data)
+            }
+            Err(error) => reply.error(errno(error)),
+        }
+    }
+
+    fn write(
+        &mut self,
+        _req: &Request,
+        _ino: u64,
+        fh: u64,
+        offset: i64,
+        data: &[u8],
+        _flags: u32,
+        reply: ReplyWrite,
+    ) {
+        let handle = match self.handles.get_mut(&fh) {
+            Some(handle) => handle,
+            None => return reply.error(libc::EBADF),
+        };
+        let buffer = match handle.data.as_mut() {
+            Some(buffer) => buffer,
+            None => return reply.error(libc::EBADF),
+        };
+        let offset = offset as usize;
+        if buffer.len() < offset + data.len() {
+            buffer.resize(offset + data.len(), 0);
+        }
+        buffer[offset..offset + data.len()].copy_from_slice(data);
+        handle.dirty = true;
+        reply.written(data.len() as u32);
+    }
+
+    fn flush(&mut self, _req: &Request, _ino: u64, fh: u64, _lock_owner: u64, reply: ReplyEmpty) {
+        match self.flush_handle(fh) {
+            Ok(()) => reply.ok(),
+            Err(error) => reply.error(error),
+        }
+    }
+
+    fn release(
+        &mut self,
+        _req: &Request,
+        _ino: u64,
+        fh: u64,
+        _flags: u32,
+        _lock_owner: u64,
+        _flush: bool,
+        reply: ReplyEmpty,
+    ) {
+        let flushed = self.flush_handle(fh);
+        self.handles.remove(&fh);
+        match flushed {
+            Ok(()) => reply.ok(),
+            Err(error) => reply.error(error),
+        }
+    }
+
+    fn readdir(
+        &mut self,
+        _req: &Request,
+        ino: u64,
+        _fh: u64,
+        offset: i64,
+        mut reply: ReplyDirectory,
+    ) {
+        let path = match self.path(ino) {
+            Some(path) => path,
+            None => return reply.error(libc::ENOENT),
+        };
+        let hyperdrive = self.hyperdrive.clone();
+        let names = match task::block_on(async { hyperdrive.read().await.readdir(&path).await }) {
+            Ok(names) => names,
+            Err(error) => return reply.error(errno(error)),
+        };
+
+        let mut entries = vec![
+            (ino, FileType::Directory, ".".to_string()),
+            (ino, FileType::Directory, "..".to_string()),
+        ];
+        for name in names {
+            let child = if path.is_empty() {
+                name.clone()
+            } else {
+                format!("{}/{}", path, name)
+            };
+            match self.entry(&child) {
+                Ok(attr) => entries.push((attr.ino, attr.kind, name)),
+                Err(error) => return reply.error(error),
+            }
+        }
+
+        for (i, (ino, kind, name)) in entries.into_iter().enumerate().skip(offset as usize) {
+            // The buffer is full
+            if reply.add(ino, (i + 1) as i64, kind, name) {
+                break;
+            }
+        }
+        reply.ok();
+    }
+
+    fn create(
+        &mut self,
+        _req: &Request,
+        parent: u64,
+        name: &OsStr,
+        mode: u32,
+        _flags: u32,
+        reply: ReplyCreate,
+    ) {
+        if let Err(error) = self.ensure_writable() {
+            return reply.error(error);
+        }
+        let path = match self.child(parent, name) {
+            Some(path) => path,
+            None => return reply.error(libc::ENOENT),
+        };
+        let hyperdrive = self.hyperdrive.clone();
+        if let Err(error) = task::block_on(async {
+            hyperdrive
+                .read()
+                .await
+                .write_file_with_mode(&path, &[], mode)
+                .await
+        }) {
+            return reply.error(errno(error));
+        }
+
+        let fh = self.next_handle;
+        self.next_handle += 1;
+        self.handles.insert(
+            fh,
+            Handle {
+                path: path.clone(),
+                data: Some(vec![]),
+                dirty: false,
+                reader: None,
+            },
+        );
+        match self.entry(&path) {
+            Ok(attr) => reply.created(&TTL, &attr, 0, fh, 0),
+            Err(error) => reply.error(error),
+        }
+    }
+}
+
+fn main() {
+    env_logger::init();
+
+    let key = name();
+    let hash = key.parse_from_hash().expect("invalid dat argument");
+    let mountpoint = mountpoint();
+    let folder = folder();
+
+    let (hyperdrive, writable) = task::block_on(async {
+        let address = "0.0.0.0:3899".parse().unwrap();
+        let (mut hyperstack, writable) = match keypair(&folder, &hash).await {
+            Some(keypair) => (
+                Hyperstack::create_in_disk(keypair, address, &folder).await,
+                true,
+            ),
+            None => (Hyperstack::in_disk(hash, address, &folder).await, false),
+        };
+        let hyperstack = hyperstack
+            .as_mut()
+            .expect("Could not start hyperdrive on the stack");
+        // File blocks are only downloaded when read, the metadata is always downloaded
+        hyperstack.with_options(FeedOptions {
+            live: true,
+            sparse: true,
+            ..FeedOptions::default()
+        });
+        let mdns = hyperstack.lan().await.expect("could not configure mdns");
+        hyperstack.with_discovery(mdns);
+
+        let mut failures = hyperstack.failures();
+        task::spawn(async move {
+            if let Some((_, error)) = failures.next().await {
+                log::error!("stopped replicating the drive: {}", error);
+            }
+        });
+        task::spawn(hyperstack.replicate());
+        let hyperdrive = hyperstack.get(&hash).expect("drive joined on creation");
+        (hyperdrive, writable)
+    });
+
+    let mut options = vec!["-o", "fsname=colmeia"];
+    if !writable {
+        options.extend(&["-o", "ro"]);
+    }
+    let options: Vec<&OsStr> = options.into_iter().map(OsStr::new).collect();
+    fuse::mount(DriveFs::new(hyperdrive, writable), &mountpoint, &options)
+        .expect("could not mount the drive");
+}
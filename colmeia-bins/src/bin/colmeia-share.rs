@@ -0,0 +1,110 @@
+use async_std::{fs, io, prelude::FutureExt, task};
+use colmeia_hyperstack::Hyperstack;
+use futures::StreamExt;
+use std::{
+    path::{Path, PathBuf},
+    time::Duration,
+};
+
+fn folder() -> PathBuf {
+    let args: Vec<String> = std::env::args().skip(1).collect();
+    args.first().expect("must have folder as argument").into()
+}
+
+fn watch() -> bool {
+    std::env::args().skip(2).any(|arg| arg == "--watch")
+}
+
+// How often the folder is checked for changes in watch mode
+const WATCH_INTERVAL: Duration = Duration::from_secs(5);
+
+/// Creates the file readable only by us
+#[cfg(unix)]
+async fn write_secret(path: &Path, contents: &str) -> io::Result<()> {
+    use async_std::{io::prelude::WriteExt, os::unix::fs::OpenOptionsExt};
+    let mut file = fs::OpenOptions::new()
+        .write(true)
+        .create_new(true)
+        .mode(0o600)
+        .open(path)
+        .await?;
+    file.write_all(contents.as_bytes()).await?;
+    file.sync_all().await
+}
+
+#[cfg(not(unix))]
+async fn write_secret(path: &Path, contents: &str) -> io::Result<()> {
+    fs::write(path, contents).await
+}
+
+/// Reuses the key of a previous share of the folder, so the drive keeps the same address
+async fn keypair(storage: &Path) -> ed25519_dalek::Keypair {
+    let path = storage.join("secret_key");
+    if let Ok(encoded) = fs::read_to_string(&path).await {
+        let bytes = hex::decode(encoded.trim()).expect("invalid secret key file");
+        let secret = hypercore::SecretKey::from_bytes(&bytes).expect("invalid secret key");
+        let public = hypercore::PublicKey::from(&secret);
+        return ed25519_dalek::Keypair { secret, public };
+    }
+
+    let keypair = hypercore::generate_keypair();
+    fs::create_dir_all(storage)
+        .await
+        .expect("could not create storage folder");
+    write_secret(&path, &hex::encode(keypair.secret.as_bytes()))
+        .await
+        .expect("could not save the secret key");
+    keypair
+}
+
+fn main() {
+    env_logger::init();
+
+    let folder = folder();
+    let watch = watch();
+    let storage = folder.join(".colmeia");
+
+    task::block_on(async {
+        let keypair = keypair(&storage).await;
+        let key = keypair.public;
+        let mut hyperstack =
+            Hyperstack::create_in_disk(keypair, "0.0.0.0:3899".parse().unwrap(), &storage)
+                .await
+                .expect("Could not start hyperdrive on the stack");
+
+        let hyperdrive = hyperstack.get(&key).expect("drive joined on creation");
+        {
+            let driver = hyperdrive.read().await;
+            driver
+                .import(&folder)
+                .await
+                .expect("could not import the folder");
+            println!("{}", hex::encode(driver.metadata.read().await.public_key()));
+        }
+
+        let mdns = hyperstack.lan().await.expect("could not configure mdns");
+        hyperstack.with_discovery(mdns);
+
+        let importing = async move {
+            // Without watching, only replication keeps going
+            if !watch {
+                return futures::future::pending().await;
+            }
+            loop {
+                task::sleep(WATCH_INTERVAL).await;
+                if let Err(error) = hyperdrive.read().await.import(&folder).await {
+                    log::warn!("failed to import {}: {:?}", folder.display(), error);
+                }
+            }
+        };
+
+        let mut failures = hyperstack.failures();
+        let stopped = async move {
+            if let Some((_, error)) = failures.next().await {
+                log::error!("could not replicate the drive: {}", error);
+            }
+        };
+        task::spawn(hyperstack.replicate());
+        importing.race(stopped).await;
+    });
+}
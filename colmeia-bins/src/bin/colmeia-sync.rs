@@ -1,31 +1,79 @@
-use async_std::task;
-use colmeia_hyperstack::{utils::PublicKeyExt, Hyperstack};
+use async_std::{prelude::FutureExt, task};
+use colmeia_hyperstack::{hyperdrive::Change, utils::PublicKeyExt, Hyperstack};
+use futures::StreamExt;
+use std::path::PathBuf;
 
 fn name() -> String {
     let args: Vec<String> = std::env::args().skip(1).collect();
     args.first().expect("must have dat name as argument").into()
 }
 
-// TODO: send to a folder
-// use std::path::PathBuf;
-// fn folder() -> PathBuf {
-//     let args: Vec<String> = std::env::args().skip(3).collect();
-//     args.first().expect("must have folder as argument").into()
-// }
+fn folder() -> PathBuf {
+    let args: Vec<String> = std::env::args().skip(2).collect();
+    args.first().expect("must have folder as argument").into()
+}
 
 fn main() {
     env_logger::init();
 
     let key = name();
     let hash = key.parse_from_hash().expect("invalid dat argument");
+    let folder = folder();
 
-    // let path = name();
     task::block_on(async {
-        let mut hyperstack = Hyperstack::in_memory(hash, "0.0.0.0:3899".parse().unwrap())
-            .await
-            .expect("Could not start hyperdrive on the stack");
+        let mut hyperstack = Hyperstack::in_disk(
+            hash,
+            "0.0.0.0:3899".parse().unwrap(),
+            folder.join(".colmeia"),
+        )
+        .await
+        .expect("Could not start hyperdrive on the stack");
         let mdns = hyperstack.lan().await.expect("could not configure mdns");
         hyperstack.with_discovery(mdns);
-        hyperstack.replicate().await;
+
+        let hyperdrive = hyperstack.get(&hash).expect("drive joined on creation");
+        let mut updates = hyperdrive.read().await.metadata_hub.subscribe();
+        let export = async move {
+            // Export what we have, then only what changed every time the metadata changes
+            let mut version = None;
+            loop {
+                {
+                    let driver = hyperdrive.read().await;
+                    let current = driver.version().await;
+                    let exported = match version {
+                        Some(version) => driver.export_changes(&folder, version).await,
+                        None => driver.export(&folder).await.map(|_| vec![]),
+                    };
+                    match exported {
+                        Ok(changes) => {
+                            for change in changes {
+                                match change {
+                                    Change::Put { name, .. } => log::info!("updated {}", name),
+                                    Change::Del { name, .. } => log::info!("removed {}", name),
+                                }
+                            }
+                            version = Some(current);
+                        }
+                        Err(error) => {
+                            log::warn!("failed to export to {}: {:?}", folder.display(), error)
+                        }
+                    }
+                }
+                if updates.next().await.is_none() {
+                    break;
+                }
+                // Entries arrive in batches, export once for all of them
+                while let Ok(Some(_)) = updates.try_next() {}
+            }
+        };
+
+        let mut failures = hyperstack.failures();
+        let stopped = async move {
+            if let Some((_, error)) = failures.next().await {
+                log::error!("could not replicate the drive: {}", error);
+            }
+        };
+        task::spawn(hyperstack.replicate());
+        export.race(stopped).await;
     });
 }
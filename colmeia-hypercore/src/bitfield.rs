@@ -0,0 +1,178 @@
+use std::ops::Range;
+
+/// Most blocks a remote can announce, which keeps the bitfield under 2MB
+pub const MAX_BLOCKS: u64 = 1 << 24;
+
+/// Tracks which blocks a remote peer has announced through `Have` messages.
+///
+/// Bits are stored most significant first on each byte, the same layout used by the
+/// RLE encoded bitfields sent over the wire by hypercore.
+#[derive(Debug, Default, Clone)]
+pub struct RemoteBitfield {
+    bytes: Vec<u8>,
+}
+
+impl RemoteBitfield {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn get(&self, index: u64) -> bool {
+        let (byte, mask) = position(index);
+        self.bytes
+            .get(byte)
+            .map(|value| value & mask != 0)
+            .unwrap_or(false)
+    }
+
+    /// Returns true if the bit changed
+    pub fn set(&mut self, index: u64, value: bool) -> bool {
+        let (byte, mask) = position(index);
+        if byte >= self.bytes.len() {
+            if !value {
+                return false;
+            }
+            self.bytes.resize(byte + 1, 0);
+        }
+
+        let previous = self.bytes[byte];
+        if value {
+            self.bytes[byte] |= mask;
+        } else {
+            self.bytes[byte] &= !mask;
+        }
+        previous != self.bytes[byte]
+    }
+
+    /// Sets every bit on the range, a whole byte at a time where possible.
+    /// Blocks past `MAX_BLOCKS` are ignored.
+    pub fn set_range(&mut self, range: Range<u64>, value: bool) {
+        let mut end = range.end.min(MAX_BLOCKS);
+        if value {
+            let bytes = ((end + 7) / 8) as usize;
+            if range.start < end && bytes > self.bytes.len() {
+                self.bytes.resize(bytes, 0);
+            }
+        } else {
+            end = end.min(self.bytes.len() as u64 * 8);
+        }
+
+        let mut index = range.start;
+        while index < end && index % 8 != 0 {
+            self.set(index, value);
+            index += 1;
+        }
+        let aligned = end - end % 8;
+        if index < aligned {
+            let fill = if value { 0xff } else { 0 };
+            for byte in &mut self.bytes[(index / 8) as usize..(aligned / 8) as usize] {
+                *byte = fill;
+            }
+            index = aligned;
+        }
+        while index < end {
+            self.set(index, value);
+            index += 1;
+        }
+    }
+
+    /// Copies a decoded bitfield starting at the `start` block.
+    /// The start is rounded down to a byte boundary, like the bitfield pages sent on `Have`.
+    /// Blocks past `MAX_BLOCKS` are ignored.
+    pub fn fill(&mut self, buffer: &[u8], start: u64) {
+        let max = (MAX_BLOCKS / 8) as usize;
+        let offset = (start / 8).min(max as u64) as usize;
+        let end = offset + buffer.len().min(max - offset);
+        let buffer = &buffer[..end - offset];
+        if end > self.bytes.len() {
+            self.bytes.resize(end, 0);
+        }
+        self.bytes[offset..end].copy_from_slice(buffer);
+    }
+
+    /// Index of the last block the remote has, if any
+    pub fn last(&self) -> Option<u64> {
+        let (byte, value) = self
+            .bytes
+            .iter()
+            .enumerate()
+            .rev()
+            .find(|(_, value)| **value != 0)?;
+        let bit = 7 - value.trailing_zeros() as u64;
+        Some(byte as u64 * 8 + bit)
+    }
+}
+
+fn position(index: u64) -> (usize, u8) {
+    ((index / 8) as usize, 128 >> (index % 8))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn sets_bits_most_significant_first() {
+        let mut bitfield = RemoteBitfield::new();
+        assert!(bitfield.set(0, true));
+        assert!(bitfield.set(9, true));
+        assert!(!bitfield.set(9, true));
+        assert_eq!(bitfield.bytes, vec![0b1000_0000, 0b0100_0000]);
+        assert!(bitfield.get(9));
+        assert!(!bitfield.get(10));
+        assert!(!bitfield.get(1000));
+    }
+
+    #[test]
+    fn clearing_past_the_end_does_not_grow() {
+        let mut bitfield = RemoteBitfield::new();
+        assert!(!bitfield.set(100, false));
+        bitfield.set_range(0..1000, false);
+        assert!(bitfield.bytes.is_empty());
+    }
+
+    #[test]
+    fn sets_ranges_across_bytes() {
+        let mut bitfield = RemoteBitfield::new();
+        bitfield.set_range(3..21, true);
+        assert_eq!(bitfield.bytes, vec![0b0001_1111, 0xff, 0b1111_1000]);
+        assert_eq!(bitfield.last(), Some(20));
+
+        bitfield.set_range(5..6, false);
+        assert_eq!(bitfield.bytes[0], 0b0001_1011);
+        bitfield.set_range(0..16, false);
+        assert_eq!(bitfield.last(), Some(20));
+        assert!((16..21).all(|index| bitfield.get(index)));
+    }
+
+    #[test]
+    fn ranges_stop_at_the_maximum() {
+        let mut bitfield = RemoteBitfield::new();
+        bitfield.set_range(MAX_BLOCKS - 3..u64::MAX, true);
+        assert_eq!(bitfield.last(), Some(MAX_BLOCKS - 1));
+        assert_eq!(bitfield.bytes.len() as u64, MAX_BLOCKS / 8);
+
+        let mut bitfield = RemoteBitfield::new();
+        bitfield.set_range(u64::MAX - 1..u64::MAX, true);
+        assert_eq!(bitfield.last(), None);
+    }
+
+    #[test]
+    fn fills_decoded_pages() {
+        let page = bitfield_rle::decode(bitfield_rle::encode(vec![0xff, 0x80])).unwrap();
+        let mut bitfield = RemoteBitfield::new();
+        bitfield.fill(&page, 16);
+        assert!(!bitfield.get(15));
+        assert!((16..25).all(|index| bitfield.get(index)));
+        assert_eq!(bitfield.last(), Some(24));
+    }
+
+    #[test]
+    fn fill_stops_at_the_maximum() {
+        let mut bitfield = RemoteBitfield::new();
+        bitfield.fill(&[0xff, 0xff], MAX_BLOCKS - 8);
+        assert_eq!(bitfield.last(), Some(MAX_BLOCKS - 1));
+        bitfield.fill(&[0xff], u64::MAX);
+        assert_eq!(bitfield.bytes.len() as u64, MAX_BLOCKS / 8);
+    }
+}
@@ -0,0 +1,338 @@
+use crate::scheduler::Scheduler;
+use anyhow::Context;
+use async_std::sync::RwLock;
+use futures::{
+    channel::mpsc::{unbounded, UnboundedReceiver, UnboundedSender},
+    StreamExt,
+};
+use std::ops::Range;
+use std::sync::{
+    atomic::{AtomicUsize, Ordering},
+    Arc, Mutex, PoisonError,
+};
+use std::time::{Duration, Instant};
+use thiserror::Error;
+
+/// How long `download` waits while no block of the range arrives
+pub const DOWNLOAD_TIMEOUT: Duration = Duration::from_secs(60);
+
+#[derive(Error, Debug)]
+pub enum DownloadError {
+    #[error("no block of {range:?} arrived for {timeout:?}")]
+    Timeout {
+        range: Range<u64>,
+        timeout: Duration,
+    },
+    #[error("feed hub closed before the download finished")]
+    Closed,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum FeedEvent {
+    /// A block is available locally, appended or downloaded.
+    /// `peer` is the hub id of the peer that sent it, if it came from the network.
+    Have { index: u64, peer: Option<usize> },
+    /// A range was asked with `FeedHub::download`
+    Download(Range<u64>),
+    /// Requests given up by a peer that left or stalled, so another peer can ask for them
+    Released(Vec<u64>),
+}
+
+/// Shared by every peer replicating the same feed, so what one peer downloads
+/// can be announced to the others.
+#[derive(Clone, Default)]
+pub struct FeedHub {
+    next_id: Arc<AtomicUsize>,
+    subscribers: Arc<Mutex<Vec<UnboundedSender<FeedEvent>>>>,
+    downloads: Arc<Mutex<Vec<Range<u64>>>>,
+    scheduler: Arc<Mutex<Scheduler>>,
+}
+
+// Keeps the range wanted until the download finishes or is dropped
+struct Wanted<'a> {
+    hub: &'a FeedHub,
+    range: Range<u64>,
+}
+
+impl Drop for Wanted<'_> {
+    fn drop(&mut self) {
+        let mut downloads = self
+            .hub
+            .downloads
+            .lock()
+            .unwrap_or_else(PoisonError::into_inner);
+        if let Some(position) = downloads.iter().position(|range| *range == self.range) {
+            downloads.remove(position);
+        }
+    }
+}
+
+impl FeedHub {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn subscribe(&self) -> UnboundedReceiver<FeedEvent> {
+        let (sender, receiver) = unbounded();
+        self.subscribers
+            .lock()
+            .unwrap_or_else(PoisonError::into_inner)
+            .push(sender);
+        receiver
+    }
+
+    pub(crate) fn register(&self) -> (usize, UnboundedReceiver<FeedEvent>) {
+        let id = self.next_id.fetch_add(1, Ordering::SeqCst);
+        (id, self.subscribe())
+    }
+
+    pub(crate) fn notify(&self, event: FeedEvent) {
+        self.subscribers
+            .lock()
+            .unwrap_or_else(PoisonError::into_inner)
+            .retain(|subscriber| subscriber.unbounded_send(event.clone()).is_ok());
+    }
+
+    pub(crate) fn reserve(&self, index: u64, peer: usize) -> bool {
+        self.scheduler
+            .lock()
+            .unwrap_or_else(PoisonError::into_inner)
+            .reserve(index, peer)
+    }
+
+    pub(crate) fn complete(&self, index: u64) {
+        self.scheduler
+            .lock()
+            .unwrap_or_else(PoisonError::into_inner)
+            .complete(index);
+    }
+
+    pub(crate) fn release(&self, peer: usize, indexes: &[u64]) {
+        if indexes.is_empty() {
+            return;
+        }
+        {
+            let mut scheduler = self
+                .scheduler
+                .lock()
+                .unwrap_or_else(PoisonError::into_inner);
+            for index in indexes {
+                scheduler.release(*index, peer);
+            }
+        }
+        self.notify(FeedEvent::Released(indexes.to_vec()));
+    }
+
+    pub(crate) fn release_peer(&self, peer: usize) {
+        let released = self
+            .scheduler
+            .lock()
+            .unwrap_or_else(PoisonError::into_inner)
+            .release_peer(peer);
+        if !released.is_empty() {
+            self.notify(FeedEvent::Released(released));
+        }
+    }
+
+    /// Ranges requested through `download` that are not local yet
+    pub fn downloads(&self) -> Vec<Range<u64>> {
+        self.downloads
+            .lock()
+            .unwrap_or_else(PoisonError::into_inner)
+            .clone()
+    }
+
+    pub fn is_wanted(&self, index: u64) -> bool {
+        self.downloads
+            .lock()
+            .unwrap_or_else(PoisonError::into_inner)
+            .iter()
+            .any(|range| range.contains(&index))
+    }
+
+    /// Asks the peers for a range of blocks, resolving once the whole range is local.
+    /// Peers in sparse mode only download the ranges asked here.
+    /// Fails with a `DownloadError` after `DOWNLOAD_TIMEOUT` without any block arriving.
+    pub async fn download<Storage>(
+        &self,
+        feed: &Arc<RwLock<hypercore::Feed<Storage>>>,
+        range: Range<u64>,
+    ) -> anyhow::Result<()>
+    where
+        Storage: random_access_storage::RandomAccess<Error = Box<dyn std::error::Error + Send + Sync>>
+            + std::fmt::Debug
+            + Send
+            + Sync,
+    {
+        self.download_with_timeout(feed, range, DOWNLOAD_TIMEOUT)
+            .await
+    }
+
+    /// Same as `download`, giving up once no block arrived for `timeout`
+    pub async fn download_with_timeout<Storage>(
+        &self,
+        feed: &Arc<RwLock<hypercore::Feed<Storage>>>,
+        range: Range<u64>,
+        timeout: Duration,
+    ) -> anyhow::Result<()>
+    where
+        Storage: random_access_storage::RandomAccess<Error = Box<dyn std::error::Error + Send + Sync>>
+            + std::fmt::Debug
+            + Send
+            + Sync,
+    {
+        // Subscribe before checking the feed, so no block is missed in between
+        let mut events = self.subscribe();
+        if feed.write().await.has_all(range.clone()) {
+            return Ok(());
+        }
+
+        self.downloads
+            .lock()
+            .unwrap_or_else(PoisonError::into_inner)
+            .push(range.clone());
+        let _wanted = Wanted {
+            hub: self,
+            range: range.clone(),
+        };
+        self.notify(FeedEvent::Download(range.clone()));
+
+        let mut deadline = Instant::now() + timeout;
+        loop {
+            let wait = deadline.saturating_duration_since(Instant::now());
+            let event = match async_std::future::timeout(wait, events.next()).await {
+                Ok(Some(event)) => event,
+                Ok(None) => return Err(DownloadError::Closed.into()),
+                Err(_) => return Err(DownloadError::Timeout { range, timeout }.into()),
+            };
+            if let FeedEvent::Have { index, .. } = event {
+                if !range.contains(&index) {
+                    continue;
+                }
+                if feed.write().await.has_all(range.clone()) {
+                    return Ok(());
+                }
+                // Still making progress
+                deadline = Instant::now() + timeout;
+            }
+        }
+    }
+
+    pub async fn download_block<Storage>(
+        &self,
+        feed: &Arc<RwLock<hypercore::Feed<Storage>>>,
+        index: u64,
+    ) -> anyhow::Result<()>
+    where
+        Storage: random_access_storage::RandomAccess<Error = Box<dyn std::error::Error + Send + Sync>>
+            + std::fmt::Debug
+            + Send
+            + Sync,
+    {
+        self.download(feed, index..index + 1).await
+    }
+
+    pub async fn download_block_with_timeout<Storage>(
+        &self,
+        feed: &Arc<RwLock<hypercore::Feed<Storage>>>,
+        index: u64,
+        timeout: Duration,
+    ) -> anyhow::Result<()>
+    where
+        Storage: random_access_storage::RandomAccess<Error = Box<dyn std::error::Error + Send + Sync>>
+            + std::fmt::Debug
+            + Send
+            + Sync,
+    {
+        self.download_with_timeout(feed, index..index + 1, timeout)
+            .await
+    }
+
+    /// Appends a block to a local feed and let every peer know about it
+    pub async fn append<Storage>(
+        &self,
+        feed: &Arc<RwLock<hypercore::Feed<Storage>>>,
+        data: &[u8],
+    ) -> anyhow::Result<u64>
+    where
+        Storage: random_access_storage::RandomAccess<Error = Box<dyn std::error::Error + Send + Sync>>
+            + std::fmt::Debug
+            + Send
+            + Sync,
+    {
+        let index = {
+            let mut feed = feed.write().await;
+            feed.append(data)
+                .await
+                .context("could not append data to feed")?;
+            feed.len() - 1
+        };
+        self.notify(FeedEvent::Have { index, peer: None });
+        Ok(index)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use async_std::task;
+    use random_access_memory::RandomAccessMemory;
+
+    async fn writable() -> Arc<RwLock<hypercore::Feed<RandomAccessMemory>>> {
+        let keypair = hypercore::generate_keypair();
+        let storage = hypercore::Storage::new_memory().await.unwrap();
+        let feed = hypercore::Feed::builder(keypair.public, storage)
+            .secret_key(keypair.secret)
+            .build()
+            .await
+            .unwrap();
+        Arc::new(RwLock::new(feed))
+    }
+
+    #[test]
+    fn local_blocks_are_not_downloaded() {
+        task::block_on(async {
+            let feed = writable().await;
+            let hub = FeedHub::new();
+            hub.append(&feed, b"block").await.unwrap();
+            hub.download(&feed, 0..1).await.unwrap();
+            assert!(hub.downloads().is_empty());
+        })
+    }
+
+    #[test]
+    fn resolves_once_the_range_is_local() {
+        task::block_on(async {
+            let feed = writable().await;
+            let hub = FeedHub::new();
+            let download = {
+                let (hub, feed) = (hub.clone(), feed.clone());
+                task::spawn(async move { hub.download(&feed, 0..2).await })
+            };
+            task::sleep(Duration::from_millis(50)).await;
+            assert_eq!(hub.downloads(), vec![0..2]);
+
+            hub.append(&feed, b"first").await.unwrap();
+            hub.append(&feed, b"second").await.unwrap();
+            download.await.unwrap();
+            assert!(hub.downloads().is_empty());
+        })
+    }
+
+    #[test]
+    fn gives_up_without_progress() {
+        task::block_on(async {
+            let feed = writable().await;
+            let hub = FeedHub::new();
+            let error = hub
+                .download_block_with_timeout(&feed, 0, Duration::from_millis(50))
+                .await
+                .unwrap_err();
+            assert!(matches!(
+                error.downcast_ref::<DownloadError>(),
+                Some(DownloadError::Timeout { .. })
+            ));
+            assert!(hub.downloads().is_empty());
+        })
+    }
+}
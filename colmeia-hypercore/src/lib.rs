@@ -1,3 +1,15 @@
+mod bitfield;
+mod hub;
+mod multiplexer;
 mod network;
+mod scheduler;
+mod seek;
+mod wants;
 
-pub use network::{Emit, PeeredFeed};
+pub use bitfield::RemoteBitfield;
+pub use hub::{DownloadError, FeedEvent, FeedHub, DOWNLOAD_TIMEOUT};
+pub use multiplexer::{FeedEmit, FeedMultiplexer, FeedOptions, Hold, RemotePeer};
+pub use network::{Emit, PeerError, PeeredFeed};
+pub use scheduler::{MAX_REQUESTS, REQUEST_TIMEOUT};
+pub use seek::{byte_offset, seek};
+pub use wants::Wants;
@@ -0,0 +1,428 @@
+use async_std::{sync::RwLock, task};
+use futures::{
+    channel::mpsc::{unbounded, UnboundedSender},
+    io::{AsyncRead, AsyncWrite},
+    FutureExt, SinkExt, StreamExt,
+};
+use hypercore_protocol as proto;
+use std::collections::{HashMap, HashSet};
+use std::sync::{
+    atomic::{AtomicUsize, Ordering},
+    Arc, Mutex, PoisonError,
+};
+
+use crate::hub::FeedHub;
+use crate::network::{Emit, PeeredFeed};
+
+/// How a feed is replicated with each peer, see `PeeredFeed`
+#[derive(Debug, Clone, Copy)]
+pub struct FeedOptions {
+    pub live: bool,
+    pub sparse: bool,
+    pub uploading: bool,
+    pub downloading: bool,
+}
+
+impl Default for FeedOptions {
+    fn default() -> Self {
+        Self {
+            live: false,
+            sparse: false,
+            uploading: true,
+            downloading: true,
+        }
+    }
+}
+
+/// An event emitted by the feed with the discovery key
+#[derive(Debug)]
+pub struct FeedEmit {
+    pub discovery_key: Vec<u8>,
+    pub emit: Emit,
+}
+
+/// What the remote told us over a connection, kept up to date while replicating
+#[derive(Debug, Clone, Default)]
+pub struct RemotePeer {
+    /// Known once the handshake is done
+    pub public_key: Option<Vec<u8>>,
+    /// Length of each feed replicated with the remote, by discovery key, as it announced
+    pub feeds: HashMap<Vec<u8>, u64>,
+}
+
+enum Command {
+    Open(Vec<u8>),
+    Close(Vec<u8>),
+    /// A `Hold` was dropped
+    Released,
+}
+
+/// Keeps the connections replicating after the channel of every feed ended, until dropped.
+/// See `FeedMultiplexer::hold`.
+pub struct Hold {
+    holds: Arc<AtomicUsize>,
+    connections: Arc<Mutex<Vec<UnboundedSender<Command>>>>,
+}
+
+impl Drop for Hold {
+    fn drop(&mut self) {
+        self.holds.fetch_sub(1, Ordering::SeqCst);
+        self.connections
+            .lock()
+            .unwrap_or_else(PoisonError::into_inner)
+            .retain(|connection| connection.unbounded_send(Command::Released).is_ok());
+    }
+}
+
+enum Incoming {
+    Client(std::io::Result<proto::Event>),
+    Command(Command),
+    Emit(FeedEmit),
+}
+
+struct SharedFeed<Storage>
+where
+    Storage: random_access_storage::RandomAccess<Error = Box<dyn std::error::Error + Send + Sync>>
+        + std::fmt::Debug
+        + Send
+        + Sync
+        + 'static,
+{
+    public_key: Vec<u8>,
+    feed: Arc<RwLock<hypercore::Feed<Storage>>>,
+    hub: FeedHub,
+    options: FeedOptions,
+}
+
+/// Feeds replicated over a connection, routed by their discovery key.
+/// Feeds added or removed are opened or closed on every connection replicating them.
+pub struct FeedMultiplexer<Storage>
+where
+    Storage: random_access_storage::RandomAccess<Error = Box<dyn std::error::Error + Send + Sync>>
+        + std::fmt::Debug
+        + Send
+        + Sync
+        + 'static,
+{
+    feeds: Arc<Mutex<HashMap<Vec<u8>, SharedFeed<Storage>>>>,
+    connections: Arc<Mutex<Vec<UnboundedSender<Command>>>>,
+    holds: Arc<AtomicUsize>,
+}
+
+impl<Storage> Clone for FeedMultiplexer<Storage>
+where
+    Storage: random_access_storage::RandomAccess<Error = Box<dyn std::error::Error + Send + Sync>>
+        + std::fmt::Debug
+        + Send
+        + Sync
+        + 'static,
+{
+    fn clone(&self) -> Self {
+        Self {
+            feeds: self.feeds.clone(),
+            connections: self.connections.clone(),
+            holds: self.holds.clone(),
+        }
+    }
+}
+
+impl<Storage> Default for FeedMultiplexer<Storage>
+where
+    Storage: random_access_storage::RandomAccess<Error = Box<dyn std::error::Error + Send + Sync>>
+        + std::fmt::Debug
+        + Send
+        + Sync
+        + 'static,
+{
+    fn default() -> Self {
+        Self {
+            feeds: Arc::new(Mutex::new(HashMap::new())),
+            connections: Arc::new(Mutex::new(Vec::new())),
+            holds: Arc::new(AtomicUsize::new(0)),
+        }
+    }
+}
+
+impl<Storage> FeedMultiplexer<Storage>
+where
+    Storage: random_access_storage::RandomAccess<Error = Box<dyn std::error::Error + Send + Sync>>
+        + std::fmt::Debug
+        + Send
+        + Sync
+        + 'static,
+{
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Starts replicating the feed, returning its discovery key
+    pub async fn add(
+        &self,
+        feed: Arc<RwLock<hypercore::Feed<Storage>>>,
+        hub: FeedHub,
+        options: FeedOptions,
+    ) -> Vec<u8> {
+        let public_key = feed.read().await.public_key().as_bytes().to_vec();
+        let discovery_key = proto::discovery_key(&public_key);
+        let shared = SharedFeed {
+            public_key: public_key.clone(),
+            feed,
+            hub,
+            options,
+        };
+        self.feeds
+            .lock()
+            .unwrap_or_else(PoisonError::into_inner)
+            .insert(discovery_key.clone(), shared);
+        self.notify(|| Command::Open(public_key.clone()));
+        discovery_key
+    }
+
+    /// Stops replicating the feed, closing its channel on every connection
+    pub fn remove(&self, discovery_key: &[u8]) {
+        let removed = self
+            .feeds
+            .lock()
+            .unwrap_or_else(PoisonError::into_inner)
+            .remove(discovery_key);
+        if removed.is_some() {
+            self.notify(|| Command::Close(discovery_key.to_vec()));
+        }
+    }
+
+    pub fn contains(&self, discovery_key: &[u8]) -> bool {
+        self.feeds
+            .lock()
+            .unwrap_or_else(PoisonError::into_inner)
+            .contains_key(discovery_key)
+    }
+
+    pub fn discovery_keys(&self) -> Vec<Vec<u8>> {
+        self.feeds
+            .lock()
+            .unwrap_or_else(PoisonError::into_inner)
+            .keys()
+            .cloned()
+            .collect()
+    }
+
+    /// Keeps replicating while the hold is alive, even once every feed ended,
+    /// so the listener can add feeds it finds on the emits, like the content feed of a drive
+    pub fn hold(&self) -> Hold {
+        self.holds.fetch_add(1, Ordering::SeqCst);
+        Hold {
+            holds: self.holds.clone(),
+            connections: self.connections.clone(),
+        }
+    }
+
+    fn notify(&self, command: impl Fn() -> Command) {
+        self.connections
+            .lock()
+            .unwrap_or_else(PoisonError::into_inner)
+            .retain(|connection| connection.unbounded_send(command()).is_ok());
+    }
+
+    fn all_ended(&self, ended: &HashSet<Vec<u8>>) -> bool {
+        self.holds.load(Ordering::SeqCst) == 0
+            && self
+                .feeds
+                .lock()
+                .unwrap_or_else(PoisonError::into_inner)
+                .keys()
+                .all(|discovery_key| ended.contains(discovery_key))
+    }
+
+    fn public_key(&self, discovery_key: &[u8]) -> Option<Vec<u8>> {
+        self.feeds
+            .lock()
+            .unwrap_or_else(PoisonError::into_inner)
+            .get(discovery_key)
+            .map(|shared| shared.public_key.clone())
+    }
+
+    fn peer(&self, channel: proto::Channel) -> Option<PeeredFeed<Storage>> {
+        let feeds = self.feeds.lock().unwrap_or_else(PoisonError::into_inner);
+        let shared = feeds.get(channel.discovery_key().as_ref())?;
+        let mut peer = PeeredFeed::new(channel, shared.feed.clone(), shared.hub.clone());
+        peer.with_live(shared.options.live)
+            .with_sparse(shared.options.sparse)
+            .with_uploading(shared.options.uploading)
+            .with_downloading(shared.options.downloading);
+        Some(peer)
+    }
+
+    /// Replicates every feed with the remote until the connection ends,
+    /// or until the channel of every feed held ended.
+    /// Returns an error if the remote misbehaved on any channel.
+    pub async fn replicate<C>(
+        &self,
+        client: proto::Protocol<C, C>,
+        rx: impl futures::Sink<FeedEmit> + Unpin,
+    ) -> anyhow::Result<()>
+    where
+        C: AsyncRead + AsyncWrite + Send + Unpin + Clone + 'static,
+    {
+        self.replicate_tracked(client, rx, Default::default()).await
+    }
+
+    /// Same as `replicate`, recording what the remote tells us on `remote`
+    pub async fn replicate_tracked<C>(
+        &self,
+        mut client: proto::Protocol<C, C>,
+        mut rx: impl futures::Sink<FeedEmit> + Unpin,
+        remote: Arc<Mutex<RemotePeer>>,
+    ) -> anyhow::Result<()>
+    where
+        C: AsyncRead + AsyncWrite + Send + Unpin + Clone + 'static,
+    {
+        let (commands_sender, mut commands) = unbounded();
+        self.connections
+            .lock()
+            .unwrap_or_else(PoisonError::into_inner)
+            .push(commands_sender);
+        let (emit_sender, mut emits) = unbounded();
+
+        // Discovery keys opened on our side
+        let mut opened = HashSet::new();
+        let mut ended = HashSet::new();
+        let mut jobs = HashMap::new();
+
+        for discovery_key in self.discovery_keys() {
+            if let Some(public_key) = self.public_key(&discovery_key) {
+                client.open(public_key).await?;
+                opened.insert(discovery_key);
+            }
+        }
+
+        loop {
+            let (incoming, _, _) = futures::future::select_all(vec![
+                client.loop_next().map(Incoming::Client).boxed(),
+                commands.select_next_some().map(Incoming::Command).boxed(),
+                emits.select_next_some().map(Incoming::Emit).boxed(),
+            ])
+            .await;
+
+            match incoming {
+                Incoming::Client(Ok(proto::Event::DiscoveryKey(discovery_key))) => {
+                    if opened.contains(&discovery_key) {
+                        continue;
+                    }
+                    if let Some(public_key) = self.public_key(&discovery_key) {
+                        client.open(public_key).await?;
+                        opened.insert(discovery_key);
+                    }
+                }
+                Incoming::Client(Ok(proto::Event::Channel(channel))) => {
+                    let discovery_key = channel.discovery_key().to_vec();
+                    let mut peer = match self.peer(channel) {
+                        Some(peer) => peer,
+                        None => {
+                            log::debug!("channel opened for a feed we don't replicate");
+                            continue;
+                        }
+                    };
+                    log::debug!("replicating feed {:?}", discovery_key);
+                    remote
+                        .lock()
+                        .unwrap_or_else(PoisonError::into_inner)
+                        .feeds
+                        .insert(discovery_key.clone(), 0);
+                    let key = discovery_key.clone();
+                    let sender = emit_sender.clone().with(move |emit| {
+                        futures::future::ok::<_, futures::channel::mpsc::SendError>(FeedEmit {
+                            discovery_key: key.clone(),
+                            emit,
+                        })
+                    });
+                    let job = task::spawn(async move { peer.replicate(sender).await });
+                    jobs.insert(discovery_key, job);
+                }
+                Incoming::Client(Ok(proto::Event::Close(discovery_key))) => {
+                    remote
+                        .lock()
+                        .unwrap_or_else(PoisonError::into_inner)
+                        .feeds
+                        .remove(&discovery_key);
+                    jobs.remove(&discovery_key);
+                    opened.remove(&discovery_key);
+                    ended.insert(discovery_key);
+                    if self.all_ended(&ended) {
+                        break;
+                    }
+                }
+                Incoming::Client(Ok(proto::Event::Handshake(public_key))) => {
+                    remote
+                        .lock()
+                        .unwrap_or_else(PoisonError::into_inner)
+                        .public_key = Some(public_key);
+                }
+                Incoming::Client(Err(error)) => {
+                    log::debug!("connection closed: {:?}", error);
+                    break;
+                }
+                Incoming::Command(Command::Open(public_key)) => {
+                    let discovery_key = proto::discovery_key(&public_key);
+                    if !opened.contains(&discovery_key) {
+                        client.open(public_key).await?;
+                        opened.insert(discovery_key);
+                    }
+                }
+                Incoming::Command(Command::Close(discovery_key)) => {
+                    remote
+                        .lock()
+                        .unwrap_or_else(PoisonError::into_inner)
+                        .feeds
+                        .remove(&discovery_key);
+                    opened.remove(&discovery_key);
+                    if let Some(job) = jobs.remove(&discovery_key) {
+                        job.cancel().await;
+                    }
+                }
+                Incoming::Command(Command::Released) => {
+                    if !ended.is_empty() && self.all_ended(&ended) {
+                        break;
+                    }
+                }
+                Incoming::Emit(FeedEmit {
+                    discovery_key,
+                    emit: Emit::Misbehaved(error),
+                }) => {
+                    // Drop the whole connection, the peer can't be trusted for any feed
+                    let _ = rx
+                        .send(FeedEmit {
+                            discovery_key,
+                            emit: Emit::Misbehaved(error.clone()),
+                        })
+                        .await;
+                    return Err(error.into());
+                }
+                Incoming::Emit(emit) => {
+                    if let Emit::RemoteLength(length) = emit.emit {
+                        remote
+                            .lock()
+                            .unwrap_or_else(PoisonError::into_inner)
+                            .feeds
+                            .insert(emit.discovery_key.clone(), length);
+                    }
+                    let finished = match emit.emit {
+                        Emit::Ended => Some(emit.discovery_key.clone()),
+                        _ => None,
+                    };
+                    // Nobody listening is not a reason to stop replicating
+                    let _ = rx.send(emit).await;
+
+                    // Checked after the emit is delivered, so the listener can still add feeds
+                    if let Some(discovery_key) = finished {
+                        jobs.remove(&discovery_key);
+                        ended.insert(discovery_key);
+                        if self.all_ended(&ended) {
+                            break;
+                        }
+                    }
+                }
+            }
+        }
+        Ok(())
+    }
+}
@@ -1,13 +1,70 @@
 use anyhow::Context;
 use async_std::sync::RwLock;
-use futures::{SinkExt, StreamExt};
+use futures::{channel::mpsc::UnboundedReceiver, FutureExt, SinkExt, StreamExt};
+use hypercore::NodeTrait;
 use hypercore_protocol as proto;
+use std::collections::{BTreeSet, HashMap, HashSet, VecDeque};
 use std::io;
+use std::ops::Range;
 use std::sync::Arc;
+use std::time::Instant;
+use thiserror::Error;
+
+use crate::bitfield::{RemoteBitfield, MAX_BLOCKS};
+use crate::hub::{FeedEvent, FeedHub};
+use crate::scheduler::{MAX_REQUESTS, REQUEST_TIMEOUT};
+use crate::seek::seek;
+use crate::wants::Wants;
 
 #[derive(Debug)]
 pub enum Emit {
     OnData,
+    /// A new block is available on the local feed
+    Appended(u64),
+    /// Every block the remote announced is available locally
+    Synced,
+    /// The remote announced more blocks, up to this length
+    RemoteLength(u64),
+    /// The remote sent invalid data and the channel was closed
+    Misbehaved(PeerError),
+    /// Neither side needs anything else and the channel was closed
+    Ended,
+}
+
+#[derive(Error, Debug, Clone)]
+pub enum PeerError {
+    #[error("peer sent an invalid signature for block {0}")]
+    InvalidSignature(u64),
+    #[error("peer sent data that could not be verified for block {index}: {reason}")]
+    InvalidProof { index: u64, reason: String },
+    #[error("peer announced an invalid range of {length} blocks from {start}")]
+    InvalidRange { start: u64, length: u64 },
+    #[error("peer sent block {0} which was not requested")]
+    Unrequested(u64),
+}
+
+/// Whether `Feed::put` failed verifying the proof, rather than on local storage
+///
+/// Hypercore errors are not typed, so this goes by the messages of the verification steps.
+fn is_verification_failure(error: &anyhow::Error) -> bool {
+    let message = error.to_string();
+    message.contains("Signature verification failed") || message.contains("Missing tree roots")
+}
+
+/// The blocks on a `Have` or `Unhave`, up to `MAX_BLOCKS`
+fn announced_range(start: u64, length: u64) -> Result<Range<u64>, PeerError> {
+    let end = start
+        .checked_add(length)
+        .ok_or(PeerError::InvalidRange { start, length })?;
+    Ok(start.min(MAX_BLOCKS)..end.min(MAX_BLOCKS))
+}
+
+const BITFIELD_PAGE: u64 = 8192;
+
+enum Incoming {
+    Remote(Option<proto::Message>),
+    Local(Option<FeedEvent>),
+    Tick,
 }
 
 pub struct PeeredFeed<Storage>
@@ -20,7 +77,31 @@ where
 {
     pub channel: proto::Channel,
     pub feed: Arc<RwLock<hypercore::Feed<Storage>>>,
-    pub remote_length: usize,
+    pub remote_length: u64,
+    live: bool,
+    sparse: bool,
+    synced: bool,
+    uploading: bool,
+    downloading: bool,
+    remote_uploading: bool,
+    remote_downloading: bool,
+    id: usize,
+    hub: FeedHub,
+    events: UnboundedReceiver<FeedEvent>,
+    remote_bitfield: RemoteBitfield,
+    remote_wants: Wants,
+    // Ranges we asked the remote with Want
+    local_wants: Wants,
+    // Blocks the remote has and we still need to request.
+    // With the requested and withdrawn ones, it has every block only the remote has.
+    pending: BTreeSet<u64>,
+    // Requests we sent and are waiting for data
+    requested: HashMap<u64, Instant>,
+    // Requests we gave up on, which the remote may still answer
+    withdrawn: HashSet<u64>,
+    max_requests: usize,
+    // Requests the remote sent and we have not answered yet
+    uploads: VecDeque<proto::schema::Request>,
 }
 
 impl<Storage> PeeredFeed<Storage>
@@ -31,22 +112,176 @@ where
         + Sync
         + 'static,
 {
+    pub fn new(
+        channel: proto::Channel,
+        feed: Arc<RwLock<hypercore::Feed<Storage>>>,
+        hub: FeedHub,
+    ) -> Self {
+        let (id, events) = hub.register();
+        Self {
+            channel,
+            feed,
+            remote_length: 0,
+            live: false,
+            sparse: false,
+            synced: false,
+            uploading: true,
+            downloading: true,
+            remote_uploading: true,
+            remote_downloading: true,
+            id,
+            hub,
+            events,
+            remote_bitfield: RemoteBitfield::new(),
+            remote_wants: Wants::new(),
+            local_wants: Wants::new(),
+            pending: BTreeSet::new(),
+            requested: HashMap::new(),
+            withdrawn: HashSet::new(),
+            max_requests: MAX_REQUESTS,
+            uploads: VecDeque::new(),
+        }
+    }
+
+    /// Keep the channel open after the first sync, announcing and requesting new blocks
+    pub fn with_live(&mut self, live: bool) -> &mut Self {
+        self.live = live;
+        self
+    }
+
+    /// Only download the ranges asked through `FeedHub::download`
+    pub fn with_sparse(&mut self, sparse: bool) -> &mut Self {
+        self.sparse = sparse;
+        self
+    }
+
+    /// Answer requests from the remote, used for seeding
+    pub fn with_uploading(&mut self, uploading: bool) -> &mut Self {
+        self.uploading = uploading;
+        self
+    }
+
+    /// Request missing blocks from the remote, used for leeching
+    pub fn with_downloading(&mut self, downloading: bool) -> &mut Self {
+        self.downloading = downloading;
+        self
+    }
+
+    /// How many requests can wait for data from this peer at the same time
+    pub fn with_max_requests(&mut self, max_requests: usize) -> &mut Self {
+        self.max_requests = max_requests;
+        self
+    }
+
     async fn on_open(&mut self) -> io::Result<()> {
+        self.send_status().await?;
+
+        if !self.downloading {
+            return Ok(());
+        }
+        if self.sparse {
+            for range in self.hub.downloads() {
+                self.want(range).await?;
+            }
+        } else {
+            let want = proto::schema::Want {
+                start: 0,
+                length: None, // must be in sizes of 8192 bytes
+            };
+            self.local_wants.add(Wants::range(want.start, want.length));
+            self.channel.want(want).await?;
+        }
+        Ok(())
+    }
+
+    async fn send_status(&mut self) -> io::Result<()> {
         let status = proto::schema::Status {
-            downloading: Some(true),
-            uploading: Some(false), // TODO what to do here
+            downloading: Some(self.downloading),
+            uploading: Some(self.uploading),
         };
-        self.channel.status(status).await?;
+        self.channel.status(status).await
+    }
 
-        let want = proto::schema::Want {
-            start: 0,
-            length: None, // must be in sizes of 8192 bytes
-        };
-        self.channel.want(want).await?;
+    async fn on_status(&mut self, message: hypercore_protocol::schema::Status) -> io::Result<()> {
+        if let Some(downloading) = message.downloading {
+            self.remote_downloading = downloading;
+        }
+        if let Some(uploading) = message.uploading {
+            self.remote_uploading = uploading;
+        }
+        if !self.remote_uploading {
+            // Nothing requested will be answered, let other peers ask for them
+            let requested: Vec<u64> = self.requested.drain().map(|(index, _)| index).collect();
+            self.withdrawn.extend(&requested);
+            self.hub.release(self.id, &requested);
+
+            if !self.live && self.downloading {
+                self.downloading = false;
+                self.send_status().await?;
+            }
+        }
+        Ok(())
+    }
+
+    /// A channel that is not live ends once we are not downloading anything
+    /// and the remote is not downloading from us
+    fn is_done(&self) -> bool {
+        !self.live
+            && !self.downloading
+            && (!self.uploading || !self.remote_downloading)
+            && self.uploads.is_empty()
+    }
+
+    /// Sends Want messages covering the range, aligned to the 8192 blocks bitfield pages
+    async fn want(&mut self, range: Range<u64>) -> io::Result<()> {
+        let start = range.start - range.start % BITFIELD_PAGE;
+        let end = range.end.saturating_add(BITFIELD_PAGE - 1) / BITFIELD_PAGE * BITFIELD_PAGE;
+        for page in (start..end).step_by(BITFIELD_PAGE as usize) {
+            if self.local_wants.contains(page) {
+                continue;
+            }
+            self.local_wants.add(page..page + BITFIELD_PAGE);
+            let want = proto::schema::Want {
+                start: page,
+                length: Some(BITFIELD_PAGE),
+            };
+            self.channel.want(want).await?;
+        }
         Ok(())
     }
 
     async fn on_data(&mut self, message: hypercore_protocol::schema::Data) -> anyhow::Result<()> {
+        if self.requested.remove(&message.index).is_none() {
+            if self.withdrawn.remove(&message.index) {
+                // Answered after we gave up on it, someone else will fetch it
+                if self.remote_bitfield.get(message.index) {
+                    self.pending.insert(message.index);
+                }
+                return Ok(());
+            }
+            return Err(PeerError::Unrequested(message.index).into());
+        }
+
+        // Without a value the proof must carry the block hash
+        if message.value.is_none() && message.nodes.is_empty() {
+            self.hub.release(self.id, &[message.index]);
+            return Err(PeerError::InvalidProof {
+                index: message.index,
+                reason: "missing the block hash".into(),
+            }
+            .into());
+        }
+
+        let signature = match message.signature {
+            Some(ref signature) => match hypercore::Signature::from_bytes(signature) {
+                Ok(signature) => Some(signature),
+                Err(_) => {
+                    self.hub.release(self.id, &[message.index]);
+                    return Err(PeerError::InvalidSignature(message.index).into());
+                }
+            },
+            None => None,
+        };
         let proof = hypercore::Proof {
             index: message.index,
             nodes: message
@@ -54,57 +289,128 @@ where
                 .iter()
                 .map(|node| hypercore::Node::new(node.index, node.hash.to_vec(), node.size))
                 .collect(),
-            signature: message
-                .signature
-                .clone()
-                .and_then(|s| hypercore::Signature::from_bytes(&s).ok()),
+            signature,
         };
 
-        self.feed
+        let written = self
+            .feed
             .write()
             .await
             .put(message.index, message.value.as_deref(), proof)
-            .await
-            .context("could not write data to feed")?;
+            .await;
+        // The feed verifies the merkle nodes and signature before writing
+        if let Err(error) = written {
+            self.hub.release(self.id, &[message.index]);
+            if is_verification_failure(&error) {
+                return Err(PeerError::InvalidProof {
+                    index: message.index,
+                    reason: error.to_string(),
+                }
+                .into());
+            }
+            return Err(error.context(format!("could not store block {}", message.index)));
+        }
 
-        Ok(())
+        self.hub.complete(message.index);
+        self.hub.notify(FeedEvent::Have {
+            index: message.index,
+            peer: Some(self.id),
+        });
+        // A request slot is free again
+        self.schedule().await
     }
 
     async fn on_have(&mut self, message: hypercore_protocol::schema::Have) -> anyhow::Result<()> {
-        // TODO implement setting the length and request data on metadata
-        if let Some(ref bitfield) = message.bitfield {
+        let announced = if let Some(ref bitfield) = message.bitfield {
+            let length = bitfield_rle::decode_len(bitfield)
+                .map_err(|e| anyhow::anyhow!(e))
+                .context("could not decode bitfield")? as u64
+                * 8;
+            // Checked before decoding, so a small message can't take all the memory
+            let announced = announced_range(message.start, length)?;
+            if announced.end - announced.start < length {
+                log::debug!("ignoring a bitfield past {} blocks", MAX_BLOCKS);
+                return Ok(());
+            }
             let buf = bitfield_rle::decode(bitfield)
                 .map_err(|e| anyhow::anyhow!(e))
                 .context("could not decode bitfield")?;
-            let bits = buf.len() * 8;
-            // TODO
-            // Compare bitfields
-            // remoteAndNotLocal(this.feed.bitfield, buf, this.remoteBitfield.littleEndian, have.start)
-            // TODO fill
-            // self.remoteBitfield.fill(buf, message.get_start());
-            if bits > self.remote_length {
-                // TODO last
-                // self.remoteLength = self.remoteBitfield.last() + 1;
+            self.remote_bitfield.fill(&buf, message.start);
+            if announced.end > self.remote_length {
+                self.remote_length = self.remote_bitfield.last().map_or(0, |last| last + 1);
             }
+            announced
         } else {
-            let mut start = message.start as usize;
-            let len = message.length.unwrap_or(1);
-            for _ in 0..len {
-                start += 1;
-                // TODO feed.bitfield is private
-                // self.remoteBitfield
-                // .set(start, !self.feed.bitfield.get(start));
-            }
-            if start > self.remote_length {
-                self.remote_length = start
+            let announced = announced_range(message.start, message.length.unwrap_or(1))?;
+            self.remote_bitfield.set_range(announced.clone(), true);
+            if !announced.is_empty() && announced.end > self.remote_length {
+                self.remote_length = announced.end;
             }
+            announced
         };
-        // .context("invalid bitfield");
 
-        // SEND REQUEST
-        // TODO loop on length
-        // Use missing data from feed
-        for index in 0..=message.start {
+        self.request_missing(announced).await
+    }
+
+    /// Queue the blocks on the range that the remote has and we don't.
+    /// Same idea as `remoteAndNotLocal` on the JS implementation.
+    async fn request_missing(&mut self, range: Range<u64>) -> anyhow::Result<()> {
+        let range = range.start..range.end.min(self.remote_length);
+        {
+            let mut feed = self.feed.write().await;
+            for index in range {
+                if self.remote_bitfield.get(index)
+                    && !self.requested.contains_key(&index)
+                    && !feed.has(index)
+                {
+                    self.pending.insert(index);
+                }
+            }
+        }
+        self.schedule().await
+    }
+
+    /// Requests pending blocks up to the in-flight limit.
+    /// Blocks requested from other peers are skipped, unless their request stalled.
+    async fn schedule(&mut self) -> anyhow::Result<()> {
+        if !self.downloading || !self.remote_uploading {
+            return Ok(());
+        }
+        let available = self.max_requests.saturating_sub(self.requested.len());
+        if available == 0 || self.pending.is_empty() {
+            return Ok(());
+        }
+
+        let mut requests = Vec::with_capacity(available);
+        {
+            let mut feed = self.feed.write().await;
+            let mut done = Vec::new();
+            for index in self.pending.iter() {
+                if requests.len() >= available {
+                    break;
+                }
+                if feed.has(*index) || !self.remote_bitfield.get(*index) {
+                    done.push(*index);
+                    continue;
+                }
+                if self.sparse && !self.hub.is_wanted(*index) {
+                    continue;
+                }
+                if self.hub.reserve(*index, self.id) {
+                    requests.push(*index);
+                }
+            }
+            for index in done.iter().chain(requests.iter()) {
+                self.pending.remove(index);
+            }
+        }
+
+        if !requests.is_empty() {
+            self.synced = false;
+        }
+        for index in requests {
+            self.withdrawn.remove(&index);
+            self.requested.insert(index, Instant::now());
             let request = proto::schema::Request {
                 index,
                 ..Default::default()
@@ -115,32 +421,60 @@ where
     }
 
     async fn on_want(&mut self, message: hypercore_protocol::schema::Want) -> anyhow::Result<()> {
-        // We only reploy to multiple of 8192 in terms of offsets and lengths for want messages
-        // since this is much easier for the bitfield, in terms of paging.
-        if (message.start & 8191 != 0) || (message.length.unwrap_or(0) & 8191 != 0) {
+        self.remote_wants
+            .add(Wants::range(message.start, message.length));
+        if !self.uploading {
             return Ok(());
         }
 
-        let feed_length = self.feed.read().await.len() - 1;
-        if self.feed.write().await.has(feed_length) {
+        let feed_length = self.feed.read().await.len();
+        if feed_length == 0 {
+            // Nothing to announce yet, new blocks are sent as they arrive in live mode
+            return Ok(());
+        }
+
+        if self.feed.write().await.has(feed_length - 1) {
             // Eagerly send the length of the feed to the otherside
             // TODO: only send this if the remote is not wanting a region
             // where this is contained in
             let have = proto::schema::Have {
-                start: feed_length as u64,
+                start: feed_length - 1,
                 ..Default::default()
             };
             self.channel.have(have).await?;
         }
+
+        // The bitfield is compressed in pages, so round the range to the pages it touches
+        // and never past the blocks we know about
+        let page_start = message.start - message.start % BITFIELD_PAGE;
+        let local_end =
+            feed_length.saturating_add(BITFIELD_PAGE - 1) / BITFIELD_PAGE * BITFIELD_PAGE;
+        let wanted_end = match message.length {
+            Some(length) => {
+                message
+                    .start
+                    .saturating_add(length)
+                    .saturating_add(BITFIELD_PAGE - 1)
+                    / BITFIELD_PAGE
+                    * BITFIELD_PAGE
+            }
+            None => local_end,
+        };
+        let page_end = wanted_end.min(local_end);
+        if page_start >= page_end {
+            return Ok(());
+        }
+
+        let length = page_end - page_start;
         let rle = self
             .feed
             .read()
             .await
             .bitfield()
-            .compress(message.start as usize, message.length.unwrap_or(0) as usize)?;
+            .compress(page_start as usize, length as usize)?;
         let have = proto::schema::Have {
-            start: message.start,
-            length: message.length,
+            start: page_start,
+            length: Some(length),
             bitfield: Some(rle),
             ack: None,
         };
@@ -148,34 +482,470 @@ where
         Ok(())
     }
 
+    async fn on_local(
+        &mut self,
+        event: FeedEvent,
+        rx: &mut (impl futures::Sink<Emit> + Unpin),
+    ) -> anyhow::Result<()> {
+        match event {
+            FeedEvent::Have { index, peer } => {
+                rx.send(Emit::Appended(index))
+                    .await
+                    .map_err(|_| anyhow::anyhow!("failed to emit appended"))?;
+
+                let from_remote = peer == Some(self.id);
+                self.pending.remove(&index);
+                if !from_remote && self.requested.remove(&index).is_some() {
+                    // Another peer was faster, no need to wait for this one
+                    self.withdrawn.insert(index);
+                    let cancel = proto::schema::Cancel {
+                        index,
+                        ..Default::default()
+                    };
+                    self.channel.cancel(cancel).await?;
+                    self.schedule().await?;
+                }
+
+                if self.live
+                    && !from_remote
+                    && self.remote_wants.contains(index)
+                    && !self.remote_bitfield.get(index)
+                {
+                    let have = proto::schema::Have {
+                        start: index,
+                        length: Some(1),
+                        ..Default::default()
+                    };
+                    self.channel.have(have).await?;
+                }
+            }
+            FeedEvent::Download(range) => {
+                if self.sparse {
+                    self.want(range.clone()).await?;
+                }
+                self.request_missing(range).await?;
+            }
+            FeedEvent::Released(indexes) => {
+                for index in indexes {
+                    if self.remote_bitfield.get(index) && !self.requested.contains_key(&index) {
+                        self.pending.insert(index);
+                    }
+                }
+                self.schedule().await?;
+            }
+        }
+        Ok(())
+    }
+
+    /// Gives up on requests that waited too long, so any peer can ask for them again
+    async fn on_tick(&mut self) -> anyhow::Result<()> {
+        let expired: Vec<u64> = self
+            .requested
+            .iter()
+            .filter(|(_, since)| since.elapsed() >= REQUEST_TIMEOUT)
+            .map(|(index, _)| *index)
+            .collect();
+        if expired.is_empty() {
+            return Ok(());
+        }
+
+        log::debug!("requests timed out: {:?}", expired);
+        for index in &expired {
+            self.requested.remove(index);
+        }
+        self.withdrawn.extend(&expired);
+        self.hub.release(self.id, &expired);
+        self.schedule().await
+    }
+
+    async fn check_synced(
+        &mut self,
+        rx: &mut (impl futures::Sink<Emit> + Unpin),
+    ) -> anyhow::Result<()> {
+        if self.synced || !self.requested.is_empty() || self.remote_length == 0 {
+            return Ok(());
+        }
+
+        // Only the blocks that were missing are checked, not every block of the remote
+        let synced = {
+            let mut feed = self.feed.write().await;
+            self.pending
+                .iter()
+                .chain(self.withdrawn.iter())
+                .all(|index| !self.remote_bitfield.get(*index) || feed.has(*index))
+        };
+        if synced {
+            self.synced = true;
+            rx.send(Emit::Synced)
+                .await
+                .map_err(|_| anyhow::anyhow!("failed to emit synced"))?;
+            if !self.live {
+                // Let the remote know we are done, so it can end the channel too
+                self.downloading = false;
+                self.send_status().await?;
+            }
+        }
+        Ok(())
+    }
+
+    fn on_unwant(&mut self, message: hypercore_protocol::schema::Unwant) {
+        self.remote_wants
+            .remove(Wants::range(message.start, message.length));
+    }
+
+    fn on_unhave(&mut self, message: hypercore_protocol::schema::Unhave) -> Result<(), PeerError> {
+        let length = message.length.unwrap_or(1);
+        let removed = announced_range(message.start, length)?;
+        if message.start == 0 && length >= self.remote_length {
+            self.remote_length = 0;
+            self.remote_bitfield = RemoteBitfield::new();
+            self.pending.clear();
+            self.withdrawn
+                .extend(self.requested.drain().map(|(index, _)| index));
+            self.hub.release_peer(self.id);
+            return Ok(());
+        }
+
+        self.remote_bitfield.set_range(removed.clone(), false);
+        let pending: Vec<u64> = self.pending.range(removed.clone()).copied().collect();
+        for index in pending {
+            self.pending.remove(&index);
+        }
+        // The remote won't answer those anymore
+        let released: Vec<u64> = self
+            .requested
+            .keys()
+            .filter(|index| removed.contains(index))
+            .copied()
+            .collect();
+        for index in &released {
+            self.requested.remove(index);
+        }
+        self.withdrawn.extend(&released);
+        self.hub.release(self.id, &released);
+        Ok(())
+    }
+
+    async fn on_request(&mut self, mut message: hypercore_protocol::schema::Request) {
+        if !self.uploading {
+            return;
+        }
+        if let Some(bytes) = message.bytes.take() {
+            // Like the JS implementation, requests for bytes we can't find are not answered
+            match seek(&self.feed, bytes).await {
+                Some((index, _)) => message.index = index,
+                None => {
+                    log::debug!("no block holds byte {} for {:?}", bytes, message);
+                    return;
+                }
+            }
+        }
+        self.uploads.push_back(message);
+    }
+
+    fn on_cancel(&mut self, message: hypercore_protocol::schema::Cancel) {
+        self.uploads.retain(|request| {
+            request.index != message.index
+                || request.hash.unwrap_or(false) != message.hash.unwrap_or(false)
+        });
+    }
+
+    async fn upload(&mut self) -> anyhow::Result<()> {
+        let message = match self.uploads.pop_front() {
+            Some(message) => message,
+            None => return Ok(()),
+        };
+
+        let data = {
+            let mut feed = self.feed.write().await;
+            if !feed.has(message.index) {
+                return Ok(());
+            }
+
+            // When hash is set, the remote only wants to verify the block, and the value is not sent
+            let hash = message.hash.unwrap_or(false);
+            let proof = match message.nodes {
+                Some(digest) => feed.proof_with_digest(message.index, digest, hash).await,
+                None => feed.proof(message.index, hash).await,
+            }
+            .context("could not create proof for request")?;
+
+            let value = if hash {
+                None
+            } else {
+                feed.get(message.index)
+                    .await
+                    .context("could not read requested block")?
+            };
+
+            proto::schema::Data {
+                index: message.index,
+                value,
+                nodes: proof
+                    .nodes
+                    .iter()
+                    .map(|node| proto::schema::data::Node {
+                        index: node.index(),
+                        hash: node.hash().to_vec(),
+                        size: node.len(),
+                    })
+                    .collect(),
+                signature: proof
+                    .signature
+                    .map(|signature| signature.to_bytes().to_vec()),
+            }
+        };
+
+        self.channel.data(data).await?;
+        Ok(())
+    }
+
+    /// Closes the channel, letting the listener know the remote can't be trusted
+    async fn misbehaved(
+        &mut self,
+        error: PeerError,
+        rx: &mut (impl futures::Sink<Emit> + Unpin),
+    ) -> anyhow::Result<()> {
+        log::warn!("closing channel with misbehaving peer: {}", error);
+        rx.send(Emit::Misbehaved(error.clone()))
+            .await
+            .map_err(|_| anyhow::anyhow!("failed to emit misbehaved"))?;
+        let close = proto::schema::Close {
+            discovery_key: None,
+        };
+        self.channel.close(close).await?;
+        Err(error.into())
+    }
+
     pub async fn replicate(
         &mut self,
         mut rx: impl futures::Sink<Emit> + Unpin + Send + 'static,
     ) -> anyhow::Result<()> {
-        while let Some(message) = self.channel.next().await {
+        let mut ticks = async_std::stream::interval(REQUEST_TIMEOUT / 2);
+        loop {
+            if self.is_done() {
+                let close = proto::schema::Close {
+                    discovery_key: None,
+                };
+                self.channel.close(close).await?;
+                break;
+            }
+
+            let local = futures::future::select(
+                self.events.next().map(Incoming::Local),
+                ticks.next().map(|_| Incoming::Tick),
+            )
+            .map(|either| either.factor_first().0);
+            // Pending uploads are only served when there is no message waiting,
+            // so a Cancel from the remote can still remove them from the queue.
+            let next = futures::future::select(self.channel.next().map(Incoming::Remote), local)
+                .map(|either| either.factor_first().0);
+
+            let incoming = if self.uploads.is_empty() {
+                next.await
+            } else {
+                match next.now_or_never() {
+                    Some(incoming) => incoming,
+                    None => {
+                        self.upload().await?;
+                        continue;
+                    }
+                }
+            };
+
+            let message = match incoming {
+                Incoming::Remote(Some(message)) => message,
+                Incoming::Remote(None) => break,
+                Incoming::Local(Some(event)) => {
+                    self.on_local(event, &mut rx).await?;
+                    continue;
+                }
+                Incoming::Local(None) => continue,
+                Incoming::Tick => {
+                    self.on_tick().await?;
+                    continue;
+                }
+            };
+
             match message {
                 proto::Message::Open(_) => {
                     self.on_open().await?;
                 }
                 proto::Message::Data(message) => {
-                    self.on_data(message).await?;
+                    if let Err(error) = self.on_data(message).await {
+                        return match error.downcast::<PeerError>() {
+                            Ok(error) => self.misbehaved(error, &mut rx).await,
+                            Err(error) => Err(error),
+                        };
+                    }
                     // Let hypercore know we have data
                     // So it can operate once again and try to open content feed
                     rx.send(Emit::OnData)
                         .await
                         .map_err(|_| anyhow::anyhow!("failed to emit ondata"))?;
+                    self.check_synced(&mut rx).await?;
                 }
                 proto::Message::Have(message) => {
-                    self.on_have(message).await?;
+                    let remote_length = self.remote_length;
+                    if let Err(error) = self.on_have(message).await {
+                        return match error.downcast::<PeerError>() {
+                            Ok(error) => self.misbehaved(error, &mut rx).await,
+                            Err(error) => Err(error),
+                        };
+                    }
+                    if self.remote_length != remote_length {
+                        rx.send(Emit::RemoteLength(self.remote_length))
+                            .await
+                            .map_err(|_| anyhow::anyhow!("failed to emit remote length"))?;
+                    }
+                    self.check_synced(&mut rx).await?;
                 }
                 proto::Message::Want(message) => {
                     self.on_want(message).await?;
                 }
+                proto::Message::Request(message) => {
+                    self.on_request(message).await;
+                }
+                proto::Message::Cancel(message) => {
+                    self.on_cancel(message);
+                }
+                proto::Message::Unwant(message) => {
+                    self.on_unwant(message);
+                }
+                proto::Message::Unhave(message) => {
+                    if let Err(error) = self.on_unhave(message) {
+                        return self.misbehaved(error, &mut rx).await;
+                    }
+                }
+                proto::Message::Status(message) => {
+                    self.on_status(message).await?;
+                }
                 event => {
                     log::debug!("received event {:?}", event);
                 }
             };
         }
+        rx.send(Emit::Ended)
+            .await
+            .map_err(|_| anyhow::anyhow!("failed to emit ended"))?;
         Ok(())
     }
 }
+
+impl<Storage> Drop for PeeredFeed<Storage>
+where
+    Storage: random_access_storage::RandomAccess<Error = Box<dyn std::error::Error + Send + Sync>>
+        + std::fmt::Debug
+        + Send
+        + Sync
+        + 'static,
+{
+    fn drop(&mut self) {
+        // Let the other peers pick up whatever was requested from this one
+        self.hub.release_peer(self.id);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{FeedMultiplexer, FeedOptions};
+    use async_std::task;
+    use random_access_memory::RandomAccessMemory;
+    use std::time::Duration;
+
+    type MemoryFeed = Arc<RwLock<hypercore::Feed<RandomAccessMemory>>>;
+
+    /// A writable feed with the blocks, and an empty copy of it to download them
+    async fn feeds(blocks: u64) -> (MemoryFeed, MemoryFeed) {
+        let keypair = hypercore::generate_keypair();
+        let storage = hypercore::Storage::new_memory().await.unwrap();
+        let mut writer = hypercore::Feed::builder(keypair.public, storage)
+            .secret_key(keypair.secret)
+            .build()
+            .await
+            .unwrap();
+        for index in 0..blocks {
+            writer
+                .append(format!("block {}", index).as_bytes())
+                .await
+                .unwrap();
+        }
+        let storage = hypercore::Storage::new_memory().await.unwrap();
+        let reader = hypercore::Feed::builder(keypair.public, storage)
+            .build()
+            .await
+            .unwrap();
+        (Arc::new(RwLock::new(writer)), Arc::new(RwLock::new(reader)))
+    }
+
+    #[test]
+    fn announced_ranges_are_bounded() {
+        assert_eq!(announced_range(10, 5).unwrap(), 10..15);
+        assert_eq!(announced_range(0, u64::MAX).unwrap(), 0..MAX_BLOCKS);
+        assert!(announced_range(MAX_BLOCKS + 1, 1).unwrap().is_empty());
+        assert!(matches!(
+            announced_range(u64::MAX, 2),
+            Err(PeerError::InvalidRange { .. })
+        ));
+    }
+
+    #[test]
+    fn only_verification_failures_are_blamed_on_the_peer() {
+        let signature = anyhow::anyhow!("Signature verification failed");
+        let roots = anyhow::anyhow!("<hypercore>: Missing tree roots needed for verify");
+        let storage = anyhow::anyhow!("No space left on device");
+        assert!(is_verification_failure(&signature));
+        assert!(is_verification_failure(&roots));
+        assert!(!is_verification_failure(&storage));
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn downloads_more_blocks_than_the_requests_in_flight() {
+        task::block_on(async {
+            let blocks = MAX_REQUESTS as u64 * 3 + 1;
+            let (writer, reader) = feeds(blocks).await;
+            let (seeder_stream, leecher_stream) =
+                async_std::os::unix::net::UnixStream::pair().unwrap();
+
+            let seeder = FeedMultiplexer::new();
+            seeder
+                .add(writer, FeedHub::new(), FeedOptions::default())
+                .await;
+            let leecher = FeedMultiplexer::new();
+            let hub = FeedHub::new();
+            leecher
+                .add(reader.clone(), hub.clone(), FeedOptions::default())
+                .await;
+
+            let seeding = task::spawn(async move {
+                let client = proto::ProtocolBuilder::new(false).connect(seeder_stream);
+                seeder.replicate(client, futures::sink::drain()).await
+            });
+            let (emits, mut emitted) = futures::channel::mpsc::unbounded();
+            let leeching = task::spawn(async move {
+                let client = proto::ProtocolBuilder::new(true).connect(leecher_stream);
+                leecher.replicate(client, emits).await
+            });
+
+            hub.download_with_timeout(&reader, 0..blocks, Duration::from_secs(5))
+                .await
+                .expect("every block is downloaded");
+            assert!(reader.write().await.has_all(0..blocks));
+            let synced = async {
+                while let Some(emit) = emitted.next().await {
+                    if let Emit::Synced = emit.emit {
+                        return;
+                    }
+                }
+                panic!("replication ended before syncing");
+            };
+            async_std::future::timeout(Duration::from_secs(5), synced)
+                .await
+                .expect("synced once every block arrived");
+            leeching.cancel().await;
+            seeding.cancel().await;
+        })
+    }
+}
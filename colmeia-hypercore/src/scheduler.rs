@@ -0,0 +1,114 @@
+use std::collections::HashMap;
+use std::time::{Duration, Instant};
+
+/// How long a request can wait for data before another peer is allowed to ask for the same block
+pub const REQUEST_TIMEOUT: Duration = Duration::from_secs(10);
+
+/// How many requests a single peer can have waiting for data
+pub const MAX_REQUESTS: usize = 16;
+
+#[derive(Debug)]
+struct Inflight {
+    peer: usize,
+    since: Instant,
+}
+
+/// Keeps track of which peer was asked for each block, so peers sharing a feed
+/// download different blocks.
+#[derive(Debug, Default)]
+pub(crate) struct Scheduler {
+    inflight: HashMap<u64, Inflight>,
+}
+
+impl Scheduler {
+    /// Returns true if the peer is allowed to request the block.
+    /// A block is only requested twice if the first request stalled.
+    pub fn reserve(&mut self, index: u64, peer: usize) -> bool {
+        match self.inflight.get(&index) {
+            Some(inflight) if inflight.since.elapsed() < REQUEST_TIMEOUT => false,
+            _ => {
+                let inflight = Inflight {
+                    peer,
+                    since: Instant::now(),
+                };
+                self.inflight.insert(index, inflight);
+                true
+            }
+        }
+    }
+
+    pub fn complete(&mut self, index: u64) {
+        self.inflight.remove(&index);
+    }
+
+    pub fn release(&mut self, index: u64, peer: usize) {
+        if matches!(self.inflight.get(&index), Some(inflight) if inflight.peer == peer) {
+            self.inflight.remove(&index);
+        }
+    }
+
+    /// Drops every request made by the peer, returning the blocks that need a new peer
+    pub fn release_peer(&mut self, peer: usize) -> Vec<u64> {
+        let released: Vec<u64> = self
+            .inflight
+            .iter()
+            .filter(|(_, inflight)| inflight.peer == peer)
+            .map(|(index, _)| *index)
+            .collect();
+        for index in &released {
+            self.inflight.remove(index);
+        }
+        released
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Makes the request for the block look older than the timeout
+    fn stall(scheduler: &mut Scheduler, index: u64) {
+        let inflight = scheduler.inflight.get_mut(&index).expect("requested");
+        inflight.since = Instant::now()
+            .checked_sub(REQUEST_TIMEOUT)
+            .expect("a clock past the timeout");
+    }
+
+    #[test]
+    fn blocks_are_requested_from_one_peer_at_a_time() {
+        let mut scheduler = Scheduler::default();
+        assert!(scheduler.reserve(0, 1));
+        assert!(!scheduler.reserve(0, 2));
+        scheduler.release(0, 2);
+        assert!(!scheduler.reserve(0, 2));
+        scheduler.release(0, 1);
+        assert!(scheduler.reserve(0, 2));
+        scheduler.complete(0);
+        assert!(scheduler.reserve(0, 1));
+    }
+
+    #[test]
+    fn stalled_requests_can_be_made_again() {
+        let mut scheduler = Scheduler::default();
+        assert!(scheduler.reserve(0, 1));
+        stall(&mut scheduler, 0);
+        assert!(scheduler.reserve(0, 2));
+        assert!(!scheduler.reserve(0, 1));
+        // The first peer lost the block, so its release keeps the new request
+        scheduler.release(0, 1);
+        assert!(!scheduler.reserve(0, 1));
+    }
+
+    #[test]
+    fn released_peers_return_their_blocks() {
+        let mut scheduler = Scheduler::default();
+        assert!(scheduler.reserve(0, 1));
+        assert!(scheduler.reserve(1, 2));
+        assert!(scheduler.reserve(2, 1));
+        let mut released = scheduler.release_peer(1);
+        released.sort_unstable();
+        assert_eq!(released, vec![0, 2]);
+        assert!(scheduler.reserve(0, 2));
+        assert!(!scheduler.reserve(1, 1));
+    }
+}
@@ -0,0 +1,107 @@
+use async_std::sync::RwLock;
+use hypercore::NodeTrait;
+
+/// Byte offset of the block on the feed, from the lengths of the merkle tree roots before it.
+/// `None` when those tree nodes are not stored locally.
+pub async fn byte_offset<Storage>(
+    feed: &RwLock<hypercore::Feed<Storage>>,
+    index: u64,
+) -> Option<u64>
+where
+    Storage: random_access_storage::RandomAccess<Error = Box<dyn std::error::Error + Send + Sync>>
+        + std::fmt::Debug
+        + Send
+        + Sync,
+{
+    if index == 0 {
+        return Some(0);
+    }
+    let roots = feed.write().await.root_hashes(index - 1).await.ok()?;
+    // Nodes never written read as zeroes
+    if roots
+        .iter()
+        .any(|node| node.hash().iter().all(|byte| *byte == 0))
+    {
+        return None;
+    }
+    Some(roots.iter().map(|node| node.len()).sum())
+}
+
+/// The block holding the byte, and the byte offset where that block starts.
+/// Searches the merkle tree, so only the tree nodes are needed, not the blocks.
+/// `None` past the end of the feed, or when the tree nodes are not stored locally.
+pub async fn seek<Storage>(
+    feed: &RwLock<hypercore::Feed<Storage>>,
+    bytes: u64,
+) -> Option<(u64, u64)>
+where
+    Storage: random_access_storage::RandomAccess<Error = Box<dyn std::error::Error + Send + Sync>>
+        + std::fmt::Debug
+        + Send
+        + Sync,
+{
+    let length = feed.read().await.len();
+    if bytes >= byte_offset(feed, length).await? {
+        return None;
+    }
+
+    // The last block starting at or before the byte
+    let (mut low, mut low_offset) = (0, 0);
+    let mut high = length;
+    while high - low > 1 {
+        let middle = low + (high - low) / 2;
+        let offset = byte_offset(feed, middle).await?;
+        if offset <= bytes {
+            low = middle;
+            low_offset = offset;
+        } else {
+            high = middle;
+        }
+    }
+    Some((low, low_offset))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use async_std::task;
+
+    #[test]
+    fn seeks_the_block_holding_the_byte() {
+        task::block_on(async {
+            let keypair = hypercore::generate_keypair();
+            let storage = hypercore::Storage::new_memory().await.unwrap();
+            let mut feed = hypercore::Feed::builder(keypair.public, storage)
+                .secret_key(keypair.secret)
+                .build()
+                .await
+                .unwrap();
+            // Blocks of 1, 2, 3... bytes, starting at 0, 1, 3, 6...
+            for size in 1..=20 {
+                feed.append(&vec![0; size]).await.unwrap();
+            }
+            let feed = RwLock::new(feed);
+
+            assert_eq!(byte_offset(&feed, 3).await, Some(6));
+            assert_eq!(seek(&feed, 0).await, Some((0, 0)));
+            assert_eq!(seek(&feed, 2).await, Some((1, 1)));
+            assert_eq!(seek(&feed, 3).await, Some((2, 3)));
+            assert_eq!(seek(&feed, 209).await, Some((19, 190)));
+            assert_eq!(seek(&feed, 210).await, None);
+        })
+    }
+
+    #[test]
+    fn unknown_offsets_are_not_guessed() {
+        task::block_on(async {
+            let keypair = hypercore::generate_keypair();
+            let storage = hypercore::Storage::new_memory().await.unwrap();
+            let feed = hypercore::Feed::builder(keypair.public, storage)
+                .build()
+                .await
+                .unwrap();
+            let feed = RwLock::new(feed);
+            assert_eq!(seek(&feed, 0).await, None);
+        })
+    }
+}
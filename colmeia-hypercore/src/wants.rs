@@ -0,0 +1,106 @@
+use std::ops::Range;
+
+/// Ranges of blocks a remote peer has asked to be notified about, using `Want` and `Unwant`.
+#[derive(Debug, Default, Clone)]
+pub struct Wants {
+    ranges: Vec<Range<u64>>,
+}
+
+impl Wants {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// A missing length on the protocol means the remote wants everything after start
+    pub fn range(start: u64, length: Option<u64>) -> Range<u64> {
+        match length {
+            Some(length) => start..start.saturating_add(length),
+            None => start..u64::MAX,
+        }
+    }
+
+    pub fn add(&mut self, range: Range<u64>) {
+        if range.start >= range.end {
+            return;
+        }
+
+        let mut merged = range;
+        self.ranges.retain(|existing| {
+            if existing.start <= merged.end && merged.start <= existing.end {
+                merged.start = merged.start.min(existing.start);
+                merged.end = merged.end.max(existing.end);
+                false
+            } else {
+                true
+            }
+        });
+        self.ranges.push(merged);
+        self.ranges.sort_by_key(|range| range.start);
+    }
+
+    pub fn remove(&mut self, range: Range<u64>) {
+        let mut remaining = Vec::with_capacity(self.ranges.len() + 1);
+        for existing in self.ranges.drain(..) {
+            if existing.end <= range.start || range.end <= existing.start {
+                remaining.push(existing);
+                continue;
+            }
+            if existing.start < range.start {
+                remaining.push(existing.start..range.start);
+            }
+            if range.end < existing.end {
+                remaining.push(range.end..existing.end);
+            }
+        }
+        self.ranges = remaining;
+    }
+
+    pub fn contains(&self, index: u64) -> bool {
+        self.ranges.iter().any(|range| range.contains(&index))
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.ranges.is_empty()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn missing_lengths_want_everything_after_start() {
+        assert_eq!(Wants::range(3, Some(2)), 3..5);
+        assert_eq!(Wants::range(3, None), 3..u64::MAX);
+        assert_eq!(Wants::range(u64::MAX - 1, Some(5)), u64::MAX - 1..u64::MAX);
+    }
+
+    #[test]
+    fn added_ranges_are_merged() {
+        let mut wants = Wants::new();
+        wants.add(10..20);
+        wants.add(0..5);
+        wants.add(5..10);
+        wants.add(30..30);
+        assert_eq!(wants.ranges, vec![0..20]);
+        wants.add(25..30);
+        wants.add(15..26);
+        assert_eq!(wants.ranges, vec![0..30]);
+    }
+
+    #[test]
+    fn removed_ranges_split_the_wanted_ones() {
+        let mut wants = Wants::new();
+        wants.add(0..10);
+        wants.add(20..30);
+        wants.remove(5..25);
+        assert_eq!(wants.ranges, vec![0..5, 25..30]);
+        assert!(wants.contains(4));
+        assert!(!wants.contains(5));
+        assert!(!wants.contains(24));
+        assert!(wants.contains(29));
+        assert!(!wants.contains(30));
+        wants.remove(Wants::range(0, None));
+        assert!(wants.is_empty());
+    }
+}
@@ -0,0 +1,363 @@
+use anyhow::Context;
+use async_std::{fs, io::prelude::WriteExt};
+use filetime::FileTime;
+use futures::TryStreamExt;
+use std::collections::{BTreeMap, HashSet};
+use std::path::{Component, Path, PathBuf};
+
+use crate::fs::StatExt;
+use crate::history::{parse_stat, Change};
+use crate::hyperdrive::Hyperdrive;
+use crate::import::is_hidden;
+use crate::schema::Stat;
+use crate::trie;
+
+/// Hidden entries are not exported, the same as they are not imported,
+/// so a drive can't write over our own storage folder
+fn is_exportable(key: &str) -> bool {
+    !Path::new(trie::normalize(key)).components().any(|component| {
+        matches!(component, Component::Normal(name) if is_hidden(&name.to_string_lossy()))
+    })
+}
+
+/// Where the entry goes inside the folder, refusing keys that would escape it,
+/// be it with `..`, through a folder that is a symlink or into a hidden folder
+async fn destination(dir: &Path, key: &str) -> anyhow::Result<PathBuf> {
+    let relative = Path::new(trie::normalize(key));
+    let inside = relative
+        .components()
+        .all(|component| matches!(component, Component::Normal(_)));
+    anyhow::ensure!(inside, "entry {} points outside of the folder", key);
+    anyhow::ensure!(is_exportable(key), "entry {} is hidden", key);
+
+    let mut ancestor = dir.to_path_buf();
+    if let Some(parent) = relative.parent() {
+        for component in parent.components() {
+            ancestor.push(component);
+            match fs::symlink_metadata(&ancestor).await {
+                Ok(metadata) if metadata.file_type().is_symlink() => {
+                    anyhow::bail!("entry {} is inside a symlink", key)
+                }
+                Ok(_) => {}
+                // Nothing further down exists yet
+                Err(_) => break,
+            }
+        }
+    }
+    Ok(dir.join(relative))
+}
+
+fn mtime(stat: &Stat) -> FileTime {
+    let millis = stat.get_mtime();
+    FileTime::from_unix_time((millis / 1000) as i64, ((millis % 1000) * 1_000_000) as u32)
+}
+
+/// Files with the same size and mtime were exported before
+async fn is_exported(path: &Path, stat: &Stat) -> bool {
+    match fs::symlink_metadata(path).await {
+        Ok(metadata) => {
+            metadata.is_file()
+                && metadata.len() == stat.get_size()
+                && FileTime::from_last_modification_time(&metadata) == mtime(stat)
+        }
+        Err(_) => false,
+    }
+}
+
+#[cfg(unix)]
+async fn set_mode(path: &Path, stat: &Stat) -> anyhow::Result<()> {
+    use std::os::unix::fs::PermissionsExt;
+    let permissions = std::fs::Permissions::from_mode(stat.get_mode() & 0o7777);
+    fs::set_permissions(path, permissions).await?;
+    Ok(())
+}
+
+#[cfg(not(unix))]
+async fn set_mode(_path: &Path, _stat: &Stat) -> anyhow::Result<()> {
+    Ok(())
+}
+
+#[cfg(unix)]
+async fn symlink(target: &str, path: &Path) -> anyhow::Result<()> {
+    if let Ok(current) = fs::read_link(path).await {
+        if current.to_str() == Some(target) {
+            return Ok(());
+        }
+    }
+    if fs::symlink_metadata(path).await.is_ok() {
+        fs::remove_file(path).await?;
+    }
+    async_std::os::unix::fs::symlink(target, path).await?;
+    Ok(())
+}
+
+#[cfg(not(unix))]
+async fn symlink(target: &str, path: &Path) -> anyhow::Result<()> {
+    log::warn!("skipping symlink {} -> {}", path.display(), target);
+    Ok(())
+}
+
+/// Removes entries deleted from the drive, sorted by name.
+/// Backwards, so folders are emptied before being removed.
+async fn remove_entries(dir: &Path, removed: &[(&str, &Stat)]) -> anyhow::Result<()> {
+    for (name, stat) in removed.iter().rev() {
+        let path = destination(dir, name).await?;
+        if fs::symlink_metadata(&path).await.is_err() {
+            continue;
+        }
+        log::debug!("removing {}", path.display());
+        let result = if stat.is_dir() {
+            fs::remove_dir(&path).await
+        } else {
+            fs::remove_file(&path).await
+        };
+        if let Err(error) = result {
+            log::warn!("could not remove {}: {}", path.display(), error);
+        }
+    }
+    Ok(())
+}
+
+impl<Storage> Hyperdrive<Storage>
+where
+    Storage: random_access_storage::RandomAccess<Error = Box<dyn std::error::Error + Send + Sync>>
+        + std::fmt::Debug
+        + Send
+        + Sync
+        + 'static,
+{
+    /// Writes the drive file tree on a folder, with the mode and mtime of each entry.
+    /// Files already exported with the same size and mtime are not written again,
+    /// and entries deleted from the drive are removed, in case an earlier export left them.
+    pub async fn export(&self, dir: impl AsRef<Path>) -> anyhow::Result<()> {
+        let dir = dir.as_ref();
+        fs::create_dir_all(dir)
+            .await
+            .with_context(|| format!("could not create {}", dir.display()))?;
+
+        let mut entries = Vec::new();
+        for node in self.trie().list("").await? {
+            if !is_exportable(&node.key) {
+                log::debug!("skipping hidden entry {}", node.key);
+                continue;
+            }
+            let stat = parse_stat(&node)?;
+            entries.push((node.key, stat));
+        }
+
+        let current: HashSet<&str> = entries.iter().map(|(name, _)| name.as_str()).collect();
+        let mut deleted = BTreeMap::new();
+        let mut history = Box::pin(self.history().await?);
+        while let Some(entry) = history.try_next().await? {
+            if let Change::Del { name, stat } = entry.change {
+                if is_exportable(&name) && !current.contains(name.as_str()) {
+                    deleted.insert(name, stat);
+                }
+            }
+        }
+        let deleted: Vec<(&str, &Stat)> = deleted
+            .iter()
+            .map(|(name, stat)| (name.as_str(), stat))
+            .collect();
+        remove_entries(dir, &deleted).await?;
+
+        self.export_entries(dir, &entries).await
+    }
+
+    /// Writes files and folders first and symlinks after them, so nothing is written
+    /// through a link. Folder modes go last, deepest first, as they may be read only.
+    async fn export_entries(&self, dir: &Path, entries: &[(String, Stat)]) -> anyhow::Result<()> {
+        for (name, stat) in entries.iter().filter(|(_, stat)| !stat.is_symlink()) {
+            self.export_entry(dir, name, stat).await?;
+        }
+        for (name, stat) in entries.iter().filter(|(_, stat)| stat.is_symlink()) {
+            let path = destination(dir, name).await?;
+            if let Some(parent) = path.parent() {
+                fs::create_dir_all(parent).await?;
+            }
+            symlink(stat.get_linkname(), &path).await?;
+        }
+
+        let mut folders: Vec<&(String, Stat)> =
+            entries.iter().filter(|(_, stat)| stat.is_dir()).collect();
+        folders.sort_by_key(|(name, _)| std::cmp::Reverse(trie::split(name).len()));
+        for (name, stat) in folders {
+            set_mode(&destination(dir, name).await?, stat).await?;
+        }
+        Ok(())
+    }
+
+    /// Applies only what changed on the drive since the `from` version to a folder
+    /// exported before, removing what was deleted. Returns the changes applied.
+    pub async fn export_changes(
+        &self,
+        dir: impl AsRef<Path>,
+        from: u64,
+    ) -> anyhow::Result<Vec<Change>> {
+        let dir = dir.as_ref();
+        let changes: Vec<Change> = self
+            .diff(from, self.version().await)
+            .await?
+            .try_filter(|change| futures::future::ready(is_exportable(change.name())))
+            .try_collect()
+            .await?;
+
+        // Removed first, as a file may be replaced by a folder of the same name
+        let deleted: Vec<(&str, &Stat)> = changes
+            .iter()
+            .filter_map(|change| match change {
+                Change::Del { name, stat } => Some((name.as_str(), stat)),
+                Change::Put { .. } => None,
+            })
+            .collect();
+        remove_entries(dir, &deleted).await?;
+
+        let puts: Vec<(String, Stat)> = changes
+            .iter()
+            .filter_map(|change| match change {
+                Change::Put { name, stat } => Some((name.clone(), stat.clone())),
+                Change::Del { .. } => None,
+            })
+            .collect();
+        self.export_entries(dir, &puts).await?;
+        Ok(changes)
+    }
+
+    /// Writes a file or creates a folder, leaving the folder mode for `export_entries`
+    async fn export_entry(&self, dir: &Path, name: &str, stat: &Stat) -> anyhow::Result<()> {
+        let path = destination(dir, name).await?;
+        if let Some(parent) = path.parent() {
+            fs::create_dir_all(parent).await?;
+        }
+
+        if stat.is_dir() {
+            // A link exported before would take what goes inside elsewhere
+            if let Ok(metadata) = fs::symlink_metadata(&path).await {
+                if metadata.file_type().is_symlink() {
+                    fs::remove_file(&path).await?;
+                }
+            }
+            fs::create_dir_all(&path).await?;
+        } else if stat.is_file() {
+            if is_exported(&path, stat).await {
+                log::debug!("{} is up to date", path.display());
+                return Ok(());
+            }
+            log::debug!("exporting {}", path.display());
+            let mut data = Box::pin(self.local_read_stream(name, ..).await?).into_async_read();
+            // Files exported before may be read only
+            if fs::symlink_metadata(&path).await.is_ok() {
+                fs::remove_file(&path).await?;
+            }
+            let mut file = fs::File::create(&path)
+                .await
+                .with_context(|| format!("could not create {}", path.display()))?;
+            async_std::io::copy(&mut data, &mut file)
+                .await
+                .with_context(|| format!("could not write {}", path.display()))?;
+            // Written out before the mtime is set
+            file.flush().await?;
+            drop(file);
+            set_mode(&path, stat).await?;
+            filetime::set_file_mtime(&path, mtime(stat))?;
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use async_std::task;
+
+    /// An empty folder for the test, removed if left by a previous run
+    fn folder(name: &str) -> PathBuf {
+        let dir =
+            std::env::temp_dir().join(format!("colmeia-export-{}-{}", name, std::process::id()));
+        let _ = std::fs::remove_dir_all(&dir);
+        std::fs::create_dir_all(&dir).unwrap();
+        dir
+    }
+
+    #[test]
+    fn destinations_stay_inside_the_folder() {
+        task::block_on(async {
+            let dir = folder("destination");
+            assert_eq!(destination(&dir, "/a/b").await.unwrap(), dir.join("a/b"));
+            assert!(destination(&dir, "a/../../b").await.is_err());
+            assert!(destination(&dir, "/a/./b").await.unwrap().ends_with("a/b"));
+            std::fs::remove_dir_all(&dir).unwrap();
+        })
+    }
+
+    #[test]
+    fn hidden_entries_are_not_exported() {
+        task::block_on(async {
+            let dir = folder("hidden");
+            assert!(destination(&dir, "/.colmeia/secret_key").await.is_err());
+            assert!(destination(&dir, "/a/.git/config").await.is_err());
+
+            let drive = crate::create_in_memory(hypercore::generate_keypair())
+                .await
+                .unwrap();
+            drive
+                .write_file("/.colmeia/secret_key", b"key")
+                .await
+                .unwrap();
+            drive.write_file("/file", b"data").await.unwrap();
+            drive.export(&dir).await.unwrap();
+            assert!(dir.join("file").exists());
+            assert!(!dir.join(".colmeia").exists());
+
+            let version = drive.version().await;
+            drive.unlink("/.colmeia/secret_key").await.unwrap();
+            std::fs::create_dir(dir.join(".colmeia")).unwrap();
+            std::fs::write(dir.join(".colmeia/secret_key"), b"ours").unwrap();
+            assert!(drive
+                .export_changes(&dir, version)
+                .await
+                .unwrap()
+                .is_empty());
+            assert!(dir.join(".colmeia/secret_key").exists());
+            std::fs::remove_dir_all(&dir).unwrap();
+        })
+    }
+
+    #[test]
+    fn exports_write_files_and_remove_deleted_entries() {
+        task::block_on(async {
+            let dir = folder("deleted");
+            let drive = crate::create_in_memory(hypercore::generate_keypair())
+                .await
+                .unwrap();
+            let large: Vec<u8> = (0..200_000).map(|byte| byte as u8).collect();
+            drive.write_file("/large", &large).await.unwrap();
+            drive.write_file("/a/b", b"data").await.unwrap();
+            drive.export(&dir).await.unwrap();
+            assert_eq!(std::fs::read(dir.join("large")).unwrap(), large);
+            assert!(dir.join("a/b").exists());
+
+            drive.unlink("/a/b").await.unwrap();
+            std::fs::write(dir.join("ours"), b"ours").unwrap();
+            drive.export(&dir).await.unwrap();
+            assert!(!dir.join("a/b").exists());
+            // Never on the drive, so left alone
+            assert!(dir.join("ours").exists());
+            std::fs::remove_dir_all(&dir).unwrap();
+        })
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn destinations_do_not_go_through_symlinks() {
+        task::block_on(async {
+            let dir = folder("symlink");
+            std::os::unix::fs::symlink("/tmp", dir.join("link")).unwrap();
+            assert!(destination(&dir, "link/file").await.is_err());
+            assert!(destination(&dir, "link/a/file").await.is_err());
+            // The link itself can be replaced
+            assert_eq!(destination(&dir, "link").await.unwrap(), dir.join("link"));
+            std::fs::remove_dir_all(&dir).unwrap();
+        })
+    }
+}
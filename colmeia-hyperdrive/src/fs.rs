@@ -0,0 +1,565 @@
+use anyhow::Context;
+use async_std::sync::RwLock;
+use colmeia_hypercore::{byte_offset, FeedHub};
+use futures::{AsyncRead, AsyncReadExt, Stream};
+use std::io;
+use std::ops::{Bound, Range, RangeBounds};
+use std::sync::Arc;
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use crate::hyperdrive::Hyperdrive;
+use crate::schema::Stat;
+use crate::trie::{self, Trie};
+
+pub(crate) const S_IFMT: u32 = 0o170_000;
+pub(crate) const S_IFDIR: u32 = 0o040_000;
+pub(crate) const S_IFREG: u32 = 0o100_000;
+pub(crate) const S_IFLNK: u32 = 0o120_000;
+
+// Same block size hyperdrive uses when writing files
+const CHUNK_SIZE: usize = 64 * 1024;
+
+pub trait StatExt {
+    fn is_dir(&self) -> bool;
+    fn is_file(&self) -> bool;
+    fn is_symlink(&self) -> bool;
+    /// The content feed blocks of a file, checked as the stat comes from the remote
+    fn content_blocks(&self) -> anyhow::Result<Range<u64>>;
+}
+
+impl StatExt for Stat {
+    fn is_dir(&self) -> bool {
+        self.get_mode() & S_IFMT == S_IFDIR
+    }
+
+    fn is_file(&self) -> bool {
+        self.get_mode() & S_IFMT == S_IFREG
+    }
+
+    fn is_symlink(&self) -> bool {
+        self.get_mode() & S_IFMT == S_IFLNK
+    }
+
+    fn content_blocks(&self) -> anyhow::Result<Range<u64>> {
+        let end = self
+            .get_offset()
+            .checked_add(self.get_blocks())
+            .context("file blocks are past the end of the content feed")?;
+        Ok(self.get_offset()..end)
+    }
+}
+
+/// Byte offset of the block on the content feed, from the local tree when possible.
+/// Otherwise the block is downloaded, receiving it stores the tree nodes before it.
+async fn block_offset<Storage>(
+    content: &Arc<RwLock<hypercore::Feed<Storage>>>,
+    hub: &FeedHub,
+    index: u64,
+) -> anyhow::Result<Option<u64>>
+where
+    Storage: random_access_storage::RandomAccess<Error = Box<dyn std::error::Error + Send + Sync>>
+        + std::fmt::Debug
+        + Send
+        + Sync,
+{
+    if let Some(offset) = byte_offset(content, index).await {
+        return Ok(Some(offset));
+    }
+    hub.download_block(content, index).await?;
+    Ok(byte_offset(content, index).await)
+}
+
+struct ReadState<Storage>
+where
+    Storage: random_access_storage::RandomAccess<Error = Box<dyn std::error::Error + Send + Sync>>
+        + std::fmt::Debug
+        + Send
+        + Sync,
+{
+    content: Arc<RwLock<hypercore::Feed<Storage>>>,
+    hub: FeedHub,
+    block: u64,
+    last_block: u64,
+    // Byte position on the file where `block` starts
+    position: u64,
+    start: u64,
+    end: u64,
+}
+
+impl<Storage> ReadState<Storage>
+where
+    Storage: random_access_storage::RandomAccess<Error = Box<dyn std::error::Error + Send + Sync>>
+        + std::fmt::Debug
+        + Send
+        + Sync,
+{
+    /// Moves to the block holding `start`, with a binary search over the byte offsets of the blocks.
+    /// `file_offset` is where the file starts on the content feed.
+    /// Stops early when the offsets are not known, and `next_chunk` skips the blocks left.
+    async fn seek(&mut self, file_offset: u64) -> anyhow::Result<()> {
+        if self.start == 0 || self.block >= self.last_block {
+            return Ok(());
+        }
+
+        let mut low = self.block;
+        let mut low_position = 0;
+        let mut high = self.last_block;
+        while high - low > 1 {
+            let middle = low + (high - low) / 2;
+            let offset = block_offset(&self.content, &self.hub, middle).await?;
+            let position = match offset.and_then(|offset| offset.checked_sub(file_offset)) {
+                Some(position) => position,
+                None => break,
+            };
+            if position <= self.start {
+                low = middle;
+                low_position = position;
+            } else {
+                high = middle;
+            }
+        }
+
+        self.block = low;
+        self.position = low_position;
+        Ok(())
+    }
+
+    async fn next_chunk(&mut self) -> anyhow::Result<Option<Vec<u8>>> {
+        while self.start < self.end && self.position < self.end && self.block < self.last_block {
+            let index = self.block;
+            self.hub.download_block(&self.content, index).await?;
+            let block = self
+                .content
+                .write()
+                .await
+                .get(index)
+                .await?
+                .with_context(|| format!("content block {} is missing", index))?;
+
+            let block_start = self.position;
+            self.position += block.len() as u64;
+            self.block += 1;
+            if self.position <= self.start {
+                continue;
+            }
+
+            let from = self.start.saturating_sub(block_start) as usize;
+            let to = (self.end - block_start).min(block.len() as u64) as usize;
+            return Ok(Some(block[from..to].to_vec()));
+        }
+        Ok(None)
+    }
+}
+
+// Directories are not stored on the trie, they exist while there are entries inside
+fn directory() -> Stat {
+    let mut stat = Stat::new();
+    stat.set_mode(S_IFDIR | 0o755);
+    stat
+}
+
+/// A new entry with the times set to now, in milliseconds like hyperdrive
+pub(crate) fn entry(mode: u32) -> Stat {
+    let now = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|duration| duration.as_millis() as u64)
+        .unwrap_or_default();
+    let mut stat = Stat::new();
+    stat.set_mode(mode);
+    stat.set_mtime(now);
+    stat.set_ctime(now);
+    stat
+}
+
+impl<Storage> Hyperdrive<Storage>
+where
+    Storage: random_access_storage::RandomAccess<Error = Box<dyn std::error::Error + Send + Sync>>
+        + std::fmt::Debug
+        + Send
+        + Sync,
+{
+    pub(crate) fn trie(&self) -> Trie<'_, Storage> {
+        Trie {
+            feed: &self.metadata,
+            hub: &self.metadata_hub,
+            version: self.version,
+        }
+    }
+
+    /// The entry at the path, following mounts
+    pub async fn stat(&self, path: &str) -> anyhow::Result<Option<Stat>> {
+        let resolved = self.resolve(path).await?;
+        // The mountpoint itself, instead of the root of the mounted drive
+        if resolved.path.is_empty() && resolved.mountpoint.is_some() {
+            return Ok(resolved.mountpoint);
+        }
+        let drive = resolved.drive.as_ref().unwrap_or(self);
+        drive.local_stat(&resolved.path).await
+    }
+
+    /// The entry at the path on this drive, without following mounts
+    pub(crate) async fn local_stat(&self, path: &str) -> anyhow::Result<Option<Stat>> {
+        let key = trie::normalize(path);
+        if key.is_empty() {
+            return Ok(Some(directory()));
+        }
+
+        if let Some(node) = self.trie().get(key).await? {
+            let value = node.value.unwrap_or_default();
+            let stat = protobuf::parse_from_bytes::<Stat>(&value)
+                .with_context(|| format!("invalid stat entry for {}", key))?;
+            return Ok(Some(stat));
+        }
+
+        match self.trie().first(key).await? {
+            Some(_) => Ok(Some(directory())),
+            None => Ok(None),
+        }
+    }
+
+    /// Names of the files and folders directly inside the path
+    pub async fn readdir(&self, path: &str) -> anyhow::Result<Vec<String>> {
+        let resolved = self.resolve(path).await?;
+        let drive = resolved.drive.as_ref().unwrap_or(self);
+        drive.local_readdir(&resolved.path).await
+    }
+
+    async fn local_readdir(&self, path: &str) -> anyhow::Result<Vec<String>> {
+        let depth = trie::split(path).len();
+        let mut names: Vec<String> = self
+            .trie()
+            .children(path)
+            .await?
+            .iter()
+            .filter_map(|node| {
+                trie::split(&node.key)
+                    .get(depth)
+                    .map(|name| name.to_string())
+            })
+            .collect();
+        names.sort();
+        names.dedup();
+        Ok(names)
+    }
+
+    /// Reads the whole file from the content feed, downloading what is missing
+    pub async fn read_file(&self, path: &str) -> anyhow::Result<Vec<u8>> {
+        let resolved = self.resolve(path).await?;
+        let drive = resolved.drive.as_ref().unwrap_or(self);
+        drive.local_read_file(&resolved.path).await
+    }
+
+    async fn local_read_file(&self, path: &str) -> anyhow::Result<Vec<u8>> {
+        let stat = self
+            .local_stat(path)
+            .await?
+            .with_context(|| format!("{} not found", path))?;
+        anyhow::ensure!(stat.is_file(), "{} is not a file", path);
+
+        let content = self
+            .content()
+            .context("content feed is not available yet")?;
+        let blocks = stat.content_blocks()?;
+        self.content_hub.download(content, blocks.clone()).await?;
+
+        // Not sized by the stat, which the remote could have made up
+        let mut data = Vec::new();
+        let mut content = content.write().await;
+        for index in blocks {
+            let block = content
+                .get(index)
+                .await?
+                .with_context(|| format!("content block {} is missing", index))?;
+            data.extend_from_slice(&block);
+        }
+        Ok(data)
+    }
+
+    /// Streams a byte range of the file, fetching content blocks as they are read.
+    /// Use `TryStreamExt::into_async_read` to get an `AsyncRead`.
+    pub async fn create_read_stream(
+        &self,
+        path: &str,
+        range: impl RangeBounds<u64>,
+    ) -> anyhow::Result<impl Stream<Item = io::Result<Vec<u8>>>>
+    where
+        Storage: 'static,
+    {
+        let resolved = self.resolve(path).await?;
+        let drive = resolved.drive.as_ref().unwrap_or(self);
+        drive.local_read_stream(&resolved.path, range).await
+    }
+
+    pub(crate) async fn local_read_stream(
+        &self,
+        path: &str,
+        range: impl RangeBounds<u64>,
+    ) -> anyhow::Result<impl Stream<Item = io::Result<Vec<u8>>>>
+    where
+        Storage: 'static,
+    {
+        let stat = self
+            .local_stat(path)
+            .await?
+            .with_context(|| format!("{} not found", path))?;
+        anyhow::ensure!(stat.is_file(), "{} is not a file", path);
+
+        let content = self
+            .content()
+            .cloned()
+            .context("content feed is not available yet")?;
+        let blocks = stat.content_blocks()?;
+        let size = stat.get_size();
+        let start = match range.start_bound() {
+            Bound::Included(start) => *start,
+            Bound::Excluded(start) => start.saturating_add(1),
+            Bound::Unbounded => 0,
+        };
+        let end = match range.end_bound() {
+            Bound::Included(end) => end.saturating_add(1),
+            Bound::Excluded(end) => *end,
+            Bound::Unbounded => size,
+        };
+
+        // Older entries may not have it set
+        let file_offset = if stat.get_byteOffset() == 0 && blocks.start > 0 {
+            block_offset(&content, &self.content_hub, blocks.start).await?
+        } else {
+            Some(stat.get_byteOffset())
+        };
+
+        let mut state = ReadState {
+            content,
+            hub: self.content_hub.clone(),
+            block: blocks.start,
+            last_block: blocks.end,
+            position: 0,
+            start: start.min(size),
+            end: end.min(size),
+        };
+        if let Some(file_offset) = file_offset {
+            state.seek(file_offset).await?;
+        }
+        Ok(futures::stream::try_unfold(state, |mut state| async move {
+            match state.next_chunk().await {
+                Ok(Some(chunk)) => Ok(Some((chunk, state))),
+                Ok(None) => Ok(None),
+                Err(error) => Err(io::Error::new(io::ErrorKind::Other, error.to_string())),
+            }
+        }))
+    }
+
+    pub(crate) async fn put_stat(&self, path: &str, stat: &Stat) -> anyhow::Result<()> {
+        let _writer = self.writer.lock().await;
+        self.put_stat_locked(path, stat).await
+    }
+
+    /// Same as `put_stat`, for callers holding the writer lock
+    async fn put_stat_locked(&self, path: &str, stat: &Stat) -> anyhow::Result<()> {
+        let key = trie::normalize(path);
+        anyhow::ensure!(!key.is_empty(), "can't replace the drive root");
+        anyhow::ensure!(self.is_writable().await, "drive is not writable");
+        let value = protobuf::Message::write_to_bytes(stat)?;
+        self.trie().put(key, value).await?;
+        Ok(())
+    }
+
+    /// Appends the data to the content feed and points the path to it
+    pub async fn write_file(&self, path: &str, data: &[u8]) -> anyhow::Result<()> {
+        self.write_file_with_mode(path, data, 0o644).await
+    }
+
+    /// Same as `write_file`, with the permission bits of the file
+    pub async fn write_file_with_mode(
+        &self,
+        path: &str,
+        data: &[u8],
+        mode: u32,
+    ) -> anyhow::Result<()> {
+        self.write_file_with(path, data, entry(S_IFREG | (mode & 0o7777)))
+            .await
+    }
+
+    /// Same as `write_file`, keeping the mode and times of the given stat
+    pub(crate) async fn write_file_with(
+        &self,
+        path: &str,
+        data: &[u8],
+        stat: Stat,
+    ) -> anyhow::Result<()> {
+        self.write_stream_with(path, data, stat).await
+    }
+
+    /// Same as `write_file_with`, appending what is read a block at a time
+    pub(crate) async fn write_stream_with(
+        &self,
+        path: &str,
+        mut reader: impl AsyncRead + Unpin,
+        mut stat: Stat,
+    ) -> anyhow::Result<()> {
+        anyhow::ensure!(self.is_writable().await, "drive is not writable");
+        let content = self
+            .content()
+            .context("content feed is not available yet")?;
+
+        // Until the entry is on the trie, so the offsets are of this file only
+        let _writer = self.writer.lock().await;
+        let (offset, byte_offset) = {
+            let content = content.read().await;
+            (content.len(), content.byte_len())
+        };
+        let mut blocks = 0;
+        let mut size = 0;
+        let mut chunk = vec![0; CHUNK_SIZE];
+        loop {
+            let mut filled = 0;
+            while filled < CHUNK_SIZE {
+                match reader.read(&mut chunk[filled..]).await? {
+                    0 => break,
+                    read => filled += read,
+                }
+            }
+            if filled == 0 {
+                break;
+            }
+            self.content_hub.append(content, &chunk[..filled]).await?;
+            blocks += 1;
+            size += filled as u64;
+        }
+
+        stat.set_size(size);
+        stat.set_blocks(blocks);
+        stat.set_offset(offset);
+        stat.set_byteOffset(byte_offset);
+        self.put_stat_locked(path, &stat).await
+    }
+
+    pub async fn mkdir(&self, path: &str) -> anyhow::Result<()> {
+        self.mkdir_with_mode(path, 0o755).await
+    }
+
+    /// Same as `mkdir`, with the permission bits of the directory
+    pub async fn mkdir_with_mode(&self, path: &str, mode: u32) -> anyhow::Result<()> {
+        self.put_stat(path, &entry(S_IFDIR | (mode & 0o7777))).await
+    }
+
+    pub async fn symlink(&self, target: &str, path: &str) -> anyhow::Result<()> {
+        let mut stat = entry(S_IFLNK | 0o777);
+        stat.set_linkname(target.to_string());
+        self.put_stat(path, &stat).await
+    }
+
+    /// Removes the entry of a file, the content stays on the content feed
+    pub async fn unlink(&self, path: &str) -> anyhow::Result<()> {
+        anyhow::ensure!(self.is_writable().await, "drive is not writable");
+        let _writer = self.writer.lock().await;
+        let deleted = self.trie().del(path).await?;
+        anyhow::ensure!(deleted, "{} not found", path);
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::hyperdrive::create_in_memory;
+    use async_std::task;
+    use futures::TryStreamExt;
+
+    fn data(length: usize) -> Vec<u8> {
+        (0..length).map(|byte| (byte % 251) as u8).collect()
+    }
+
+    #[test]
+    fn byte_offsets_come_from_the_tree() {
+        task::block_on(async {
+            let drive = create_in_memory(hypercore::generate_keypair())
+                .await
+                .unwrap();
+            drive
+                .write_file("/a", &data(3 * CHUNK_SIZE + 10))
+                .await
+                .unwrap();
+            let content = drive.content().unwrap();
+            assert_eq!(byte_offset(content, 0).await, Some(0));
+            assert_eq!(byte_offset(content, 3).await, Some(3 * CHUNK_SIZE as u64));
+        })
+    }
+
+    #[test]
+    fn seeks_the_block_of_the_range() {
+        task::block_on(async {
+            let drive = create_in_memory(hypercore::generate_keypair())
+                .await
+                .unwrap();
+            drive.write_file("/before", &data(100)).await.unwrap();
+            let file = data(5 * CHUNK_SIZE + 100);
+            drive.write_file("/file", &file).await.unwrap();
+
+            let start = 3 * CHUNK_SIZE + 7;
+            let mut state = ReadState {
+                content: drive.content().cloned().unwrap(),
+                hub: drive.content_hub.clone(),
+                block: 1,
+                last_block: 7,
+                position: 0,
+                start: start as u64,
+                end: file.len() as u64,
+            };
+            state.seek(100).await.unwrap();
+            assert_eq!(state.block, 4);
+            assert_eq!(state.position, 3 * CHUNK_SIZE as u64);
+
+            let read: Vec<u8> = drive
+                .create_read_stream("/file", start as u64..=start as u64 + 9)
+                .await
+                .unwrap()
+                .try_concat()
+                .await
+                .unwrap();
+            assert_eq!(read, &file[start..start + 10]);
+        })
+    }
+
+    #[test]
+    fn streams_are_written_a_block_at_a_time() {
+        task::block_on(async {
+            let drive = create_in_memory(hypercore::generate_keypair())
+                .await
+                .unwrap();
+            let file = data(2 * CHUNK_SIZE + 5);
+            // Reads of a few bytes still fill whole blocks
+            let reader = futures::stream::iter(file.chunks(1000).map(|chunk| Ok(chunk.to_vec())))
+                .into_async_read();
+            drive
+                .write_stream_with("/file", reader, entry(S_IFREG | 0o644))
+                .await
+                .unwrap();
+
+            let stat = drive.stat("/file").await.unwrap().unwrap();
+            assert_eq!(stat.get_blocks(), 3);
+            assert_eq!(stat.get_size(), file.len() as u64);
+            assert_eq!(drive.read_file("/file").await.unwrap(), file);
+        })
+    }
+
+    #[test]
+    fn ranges_past_the_end_are_clamped() {
+        task::block_on(async {
+            let drive = create_in_memory(hypercore::generate_keypair())
+                .await
+                .unwrap();
+            let file = data(CHUNK_SIZE + 10);
+            drive.write_file("/file", &file).await.unwrap();
+
+            let read: Vec<u8> = drive
+                .create_read_stream("/file", CHUNK_SIZE as u64..=u64::MAX)
+                .await
+                .unwrap()
+                .try_concat()
+                .await
+                .unwrap();
+            assert_eq!(read, &file[CHUNK_SIZE..]);
+        })
+    }
+}
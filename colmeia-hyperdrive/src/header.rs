@@ -0,0 +1,138 @@
+use thiserror::Error;
+
+use crate::schema::{Header, Index};
+
+#[derive(Error, Debug)]
+pub enum HeaderError {
+    #[error("the first metadata entry is not a feed header: {0}")]
+    Malformed(#[from] protobuf::ProtobufError),
+    #[error("the feed is a {0:?}, not a hyperdrive")]
+    NotHyperdrive(String),
+    #[error("the hyperdrive header has no content feed key")]
+    MissingContent,
+    #[error("the content feed key on the hyperdrive header is not a valid public key")]
+    InvalidContentKey,
+}
+
+/// The first entry of the metadata feed, pointing to the content feed of the drive
+#[derive(Debug, Clone, PartialEq)]
+pub struct HyperdriveHeader {
+    pub content: hypercore::PublicKey,
+}
+
+impl HyperdriveHeader {
+    pub fn new(content: hypercore::PublicKey) -> Self {
+        Self { content }
+    }
+
+    /// Parses the header, rejecting feeds of other kinds, like plain hypercores or hypertries.
+    /// Drives are hypertries with the content key on the metadata,
+    /// and drives from before hyperdrive 10 start with an `Index` instead.
+    pub fn parse(bytes: &[u8]) -> Result<Self, HeaderError> {
+        let header = protobuf::parse_from_bytes::<Header>(bytes)?;
+        let content = match (header.get_field_type(), header.get_subtype()) {
+            ("hypertrie", "hyperdrive") if header.has_metadata() => header.get_metadata(),
+            ("hypertrie", "hyperdrive") => return Err(HeaderError::MissingContent),
+            ("hypertrie", "") => return Err(HeaderError::NotHyperdrive("hypertrie".to_string())),
+            ("hypertrie", subtype) => return Err(HeaderError::NotHyperdrive(subtype.to_string())),
+            ("hyperdrive", _) => return Self::parse_index(bytes),
+            (kind, _) => return Err(HeaderError::NotHyperdrive(kind.to_string())),
+        };
+        let content = hypercore::PublicKey::from_bytes(content)
+            .map_err(|_| HeaderError::InvalidContentKey)?;
+        Ok(Self { content })
+    }
+
+    fn parse_index(bytes: &[u8]) -> Result<Self, HeaderError> {
+        let index = protobuf::parse_from_bytes::<Index>(bytes)?;
+        if !index.has_content() {
+            return Err(HeaderError::MissingContent);
+        }
+        let content = hypercore::PublicKey::from_bytes(index.get_content())
+            .map_err(|_| HeaderError::InvalidContentKey)?;
+        Ok(Self { content })
+    }
+
+    /// The hypertrie header hyperdrive 10 writes
+    pub fn to_bytes(&self) -> Result<Vec<u8>, HeaderError> {
+        let mut header = Header::new();
+        header.set_field_type("hypertrie".to_string());
+        header.set_subtype("hyperdrive".to_string());
+        header.set_metadata(self.content.as_bytes().to_vec());
+        Ok(protobuf::Message::write_to_bytes(&header)?)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn content() -> hypercore::PublicKey {
+        hypercore::generate_keypair().public
+    }
+
+    fn header(kind: &str, subtype: &str, metadata: &[u8]) -> Vec<u8> {
+        let mut header = Header::new();
+        header.set_field_type(kind.to_string());
+        header.set_subtype(subtype.to_string());
+        header.set_metadata(metadata.to_vec());
+        protobuf::Message::write_to_bytes(&header).unwrap()
+    }
+
+    #[test]
+    fn parses_hypertrie_headers() {
+        let content = content();
+        let bytes = header("hypertrie", "hyperdrive", content.as_bytes());
+        assert_eq!(HyperdriveHeader::parse(&bytes).unwrap().content, content);
+    }
+
+    #[test]
+    fn round_trips() {
+        let header = HyperdriveHeader::new(content());
+        let parsed = protobuf::parse_from_bytes::<Header>(&header.to_bytes().unwrap()).unwrap();
+        assert_eq!(parsed.get_field_type(), "hypertrie");
+        assert_eq!(parsed.get_subtype(), "hyperdrive");
+        assert_eq!(
+            HyperdriveHeader::parse(&header.to_bytes().unwrap()).unwrap(),
+            header
+        );
+    }
+
+    #[test]
+    fn parses_legacy_indexes() {
+        let content = content();
+        let mut index = Index::new();
+        index.set_field_type("hyperdrive".to_string());
+        index.set_content(content.as_bytes().to_vec());
+        let bytes = protobuf::Message::write_to_bytes(&index).unwrap();
+        assert_eq!(HyperdriveHeader::parse(&bytes).unwrap().content, content);
+    }
+
+    #[test]
+    fn rejects_other_feeds() {
+        let content = content();
+        let plain = header("hypertrie", "", content.as_bytes());
+        assert!(matches!(
+            HyperdriveHeader::parse(&plain),
+            Err(HeaderError::NotHyperdrive(kind)) if kind == "hypertrie"
+        ));
+        let other = header("hyperbee", "", content.as_bytes());
+        assert!(matches!(
+            HyperdriveHeader::parse(&other),
+            Err(HeaderError::NotHyperdrive(kind)) if kind == "hyperbee"
+        ));
+        assert!(matches!(
+            HyperdriveHeader::parse(b"not a header"),
+            Err(HeaderError::Malformed(_))
+        ));
+    }
+
+    #[test]
+    fn rejects_invalid_content_keys() {
+        let bytes = header("hypertrie", "hyperdrive", b"short");
+        assert!(matches!(
+            HyperdriveHeader::parse(&bytes),
+            Err(HeaderError::InvalidContentKey)
+        ));
+    }
+}
@@ -0,0 +1,324 @@
+use anyhow::Context;
+use colmeia_hypercore::FeedEvent;
+use futures::{channel::mpsc::UnboundedReceiver, stream, Stream, StreamExt, TryStreamExt};
+use std::collections::{BTreeSet, VecDeque};
+
+use crate::hyperdrive::Hyperdrive;
+use crate::schema::Stat;
+use crate::trie::{self, Node};
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum Change {
+    Put {
+        name: String,
+        stat: Stat,
+    },
+    /// The entry was removed, with the stat it had before
+    Del {
+        name: String,
+        stat: Stat,
+    },
+}
+
+impl Change {
+    pub fn name(&self) -> &str {
+        match self {
+            Change::Put { name, .. } | Change::Del { name, .. } => name,
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct HistoryEntry {
+    /// The drive version right after the change, to be used with `checkout`
+    pub version: u64,
+    pub change: Change,
+}
+
+struct Watcher<Storage>
+where
+    Storage: random_access_storage::RandomAccess<Error = Box<dyn std::error::Error + Send + Sync>>
+        + std::fmt::Debug
+        + Send
+        + Sync,
+{
+    hyperdrive: Hyperdrive<Storage>,
+    events: UnboundedReceiver<FeedEvent>,
+    prefix: String,
+    version: u64,
+    pending: VecDeque<Change>,
+}
+
+impl<Storage> Watcher<Storage>
+where
+    Storage: random_access_storage::RandomAccess<Error = Box<dyn std::error::Error + Send + Sync>>
+        + std::fmt::Debug
+        + Send
+        + Sync,
+{
+    async fn next_change(&mut self) -> Option<anyhow::Result<Change>> {
+        while self.pending.is_empty() {
+            loop {
+                if let FeedEvent::Have { .. } = self.events.next().await? {
+                    break;
+                }
+            }
+            // Blocks arrive in batches, diff once for all of them
+            while let Ok(Some(_)) = self.events.try_next() {}
+
+            if let Err(error) = self.load_changes().await {
+                return Some(Err(error));
+            }
+        }
+        self.pending.pop_front().map(Ok)
+    }
+
+    async fn load_changes(&mut self) -> anyhow::Result<()> {
+        let version = self.hyperdrive.version().await;
+        if version <= self.version {
+            return Ok(());
+        }
+        let changes: Vec<Change> = self
+            .hyperdrive
+            .diff(self.version, version)
+            .await?
+            .try_collect()
+            .await?;
+        self.version = version;
+
+        let prefix = trie::split(&self.prefix);
+        self.pending.extend(
+            changes
+                .into_iter()
+                .filter(|change| trie::split(change.name()).starts_with(&prefix)),
+        );
+        Ok(())
+    }
+}
+
+pub(crate) fn parse_stat(node: &Node) -> anyhow::Result<Stat> {
+    let value = node.value.as_deref().unwrap_or_default();
+    protobuf::parse_from_bytes::<Stat>(value)
+        .with_context(|| format!("invalid stat entry for {}", node.key))
+}
+
+impl<Storage> Hyperdrive<Storage>
+where
+    Storage: random_access_storage::RandomAccess<Error = Box<dyn std::error::Error + Send + Sync>>
+        + std::fmt::Debug
+        + Send
+        + Sync,
+{
+    /// What the metadata entry at `seq` changed on the drive.
+    /// Deletions rewrite the node closest to the removed key without the pointer to it,
+    /// so they are found comparing the pointers with the trie before it.
+    async fn changes(&self, seq: u64) -> anyhow::Result<Vec<Change>> {
+        let node = self.trie().node(seq).await?;
+        if node.value.is_some() && !node.hidden {
+            let before = self.checkout(seq).trie().get(&node.key).await?;
+            let rewritten = match &before {
+                Some(before) => before.value == node.value && before.flags == node.flags,
+                None => false,
+            };
+            if !rewritten {
+                let stat = parse_stat(&node)?;
+                return Ok(vec![Change::Put {
+                    name: node.key,
+                    stat,
+                }]);
+            }
+        }
+
+        let deleted = match self.checkout(seq).trie().deleted_by(&node).await? {
+            Some(deleted) => self.trie().node(deleted).await?,
+            None => return Ok(vec![]),
+        };
+        if deleted.value.is_none() || deleted.hidden {
+            return Ok(vec![]);
+        }
+        let stat = parse_stat(&deleted)?;
+        Ok(vec![Change::Del {
+            name: deleted.key,
+            stat,
+        }])
+    }
+
+    /// Streams every put and del on the drive, from the oldest to the current version
+    pub async fn history(
+        &self,
+    ) -> anyhow::Result<impl Stream<Item = anyhow::Result<HistoryEntry>> + '_> {
+        let version = self.version().await;
+        // Block 0 is the drive header
+        let entries = stream::try_unfold(1, move |seq| async move {
+            if seq >= version {
+                return Ok(None);
+            }
+            let changes = self.changes(seq).await?;
+            let entries = changes.into_iter().map(move |change| {
+                Ok(HistoryEntry {
+                    version: seq + 1,
+                    change,
+                })
+            });
+            Ok::<_, anyhow::Error>(Some((stream::iter(entries), seq + 1)))
+        });
+        Ok(entries.try_flatten())
+    }
+
+    /// The changes needed to go from one version of the drive to another, sorted by name.
+    /// Only the keys touched by the metadata entries in between are compared,
+    /// by value, as deletions may rewrite nodes without changing them.
+    pub async fn diff(
+        &self,
+        from: u64,
+        to: u64,
+    ) -> anyhow::Result<impl Stream<Item = anyhow::Result<Change>>> {
+        // Block 0 is the drive header
+        let mut touched = BTreeSet::new();
+        for seq in from.min(to).max(1)..from.max(to) {
+            for change in self.changes(seq).await? {
+                touched.insert(change.name().to_string());
+            }
+        }
+
+        let (before, after) = (self.checkout(from), self.checkout(to));
+        let mut changes = Vec::new();
+        for name in touched {
+            let previous = before.trie().get(&name).await?;
+            match after.trie().get(&name).await? {
+                Some(node) => {
+                    if previous.map(|previous| previous.value) != Some(node.value.clone()) {
+                        let stat = parse_stat(&node)?;
+                        changes.push(Change::Put { name, stat });
+                    }
+                }
+                None => {
+                    if let Some(previous) = previous {
+                        let stat = parse_stat(&previous)?;
+                        changes.push(Change::Del { name, stat });
+                    }
+                }
+            }
+        }
+        Ok(stream::iter(changes.into_iter().map(Ok)))
+    }
+
+    /// Streams the changes under the prefix as the metadata feed grows,
+    /// be it from our own writes or entries downloaded from peers
+    pub async fn watch(&self, prefix: &str) -> impl Stream<Item = anyhow::Result<Change>>
+    where
+        Storage: 'static,
+    {
+        let watcher = Watcher {
+            events: self.metadata_hub.subscribe(),
+            hyperdrive: self.with_version(None),
+            prefix: prefix.to_string(),
+            version: self.version().await,
+            pending: VecDeque::new(),
+        };
+        stream::unfold(watcher, |mut watcher| async move {
+            let change = watcher.next_change().await?;
+            Some((change, watcher))
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::hyperdrive::create_in_memory;
+    use async_std::task;
+
+    fn summary(change: &Change) -> (&'static str, String) {
+        match change {
+            Change::Put { name, .. } => ("put", name.clone()),
+            Change::Del { name, .. } => ("del", name.clone()),
+        }
+    }
+
+    /// Puts five entries, removes them all and puts one of them back
+    async fn changed_drive() -> Hyperdrive<random_access_memory::RandomAccessMemory> {
+        let drive = create_in_memory(hypercore::generate_keypair())
+            .await
+            .unwrap();
+        for name in &["/a", "/b", "/c/d", "/c/e", "/f"] {
+            drive.write_file(name, name.as_bytes()).await.unwrap();
+        }
+        for name in &["/b", "/c/d", "/f", "/c/e", "/a"] {
+            drive.unlink(name).await.unwrap();
+        }
+        drive.write_file("/b", b"again").await.unwrap();
+        drive
+    }
+
+    async fn diff(
+        drive: &Hyperdrive<random_access_memory::RandomAccessMemory>,
+        from: u64,
+        to: u64,
+    ) -> Vec<(&'static str, String)> {
+        drive
+            .diff(from, to)
+            .await
+            .unwrap()
+            .map_ok(|change| summary(&change))
+            .try_collect()
+            .await
+            .unwrap()
+    }
+
+    #[test]
+    fn history_finds_every_deletion() {
+        task::block_on(async {
+            let drive = changed_drive().await;
+
+            let history: Vec<(u64, &str, String)> = drive
+                .history()
+                .await
+                .unwrap()
+                .map_ok(|entry| {
+                    let (kind, name) = summary(&entry.change);
+                    (entry.version, kind, name)
+                })
+                .try_collect()
+                .await
+                .unwrap();
+            let expected: Vec<(u64, &str, String)> = vec![
+                (2, "put", "a"),
+                (3, "put", "b"),
+                (4, "put", "c/d"),
+                (5, "put", "c/e"),
+                (6, "put", "f"),
+                (7, "del", "b"),
+                (8, "del", "c/d"),
+                (9, "del", "f"),
+                (10, "del", "c/e"),
+                (11, "del", "a"),
+                (12, "put", "b"),
+            ]
+            .into_iter()
+            .map(|(version, kind, name)| (version, kind, name.to_string()))
+            .collect();
+            assert_eq!(history, expected);
+        })
+    }
+
+    #[test]
+    fn diffs_compare_the_keys_touched_in_between() {
+        task::block_on(async {
+            let drive = changed_drive().await;
+            let all = |kind| {
+                vec!["a", "b", "c/d", "c/e", "f"]
+                    .into_iter()
+                    .map(move |name| (kind, name.to_string()))
+                    .collect::<Vec<_>>()
+            };
+            let mut removed = all("del");
+            // Removed and put back with another value
+            removed[1].0 = "put";
+            assert_eq!(diff(&drive, 6, 12).await, removed);
+            assert_eq!(diff(&drive, 12, 6).await, all("put"));
+            assert_eq!(diff(&drive, 1, 6).await, all("put"));
+            assert!(diff(&drive, 12, 12).await.is_empty());
+        })
+    }
+}
@@ -1,7 +1,36 @@
 use anyhow::Context;
-use async_std::sync::RwLock;
+use async_std::sync::{Mutex, RwLock};
+use colmeia_hypercore::FeedHub;
+use once_cell::sync::OnceCell;
+use std::path::Path;
 use std::sync::Arc;
 
+use crate::fs::StatExt;
+use crate::header::HyperdriveHeader;
+use crate::history::parse_stat;
+use crate::mount::Mounts;
+use crate::storage::{DiskStorage, FeedStorage, MemoryStorage};
+
+/// Derives the content feed keys from the metadata secret key, the same way hyperdrive does,
+/// using libsodium `crypto_kdf_derive_from_key` with the "hyperdri" context and subkey 1
+pub fn content_keypair(
+    secret_key: &hypercore::SecretKey,
+) -> anyhow::Result<ed25519_dalek::Keypair> {
+    let mut salt = [0u8; 16];
+    salt[..8].copy_from_slice(&1u64.to_le_bytes());
+    let seed = blake2b_simd::Params::new()
+        .hash_length(32)
+        .key(secret_key.as_bytes())
+        .salt(&salt)
+        .personal(b"hyperdri")
+        .hash(&[]);
+
+    let secret = hypercore::SecretKey::from_bytes(seed.as_bytes())
+        .map_err(|_| anyhow::anyhow!("could not derive the content secret key"))?;
+    let public = hypercore::PublicKey::from(&secret);
+    Ok(ed25519_dalek::Keypair { secret, public })
+}
+
 pub struct Hyperdrive<Storage>
 where
     Storage: random_access_storage::RandomAccess<Error = Box<dyn std::error::Error + Send + Sync>>
@@ -10,8 +39,15 @@ where
         + Sync,
 {
     pub metadata: Arc<RwLock<hypercore::Feed<Storage>>>,
-    pub content: Option<Arc<RwLock<hypercore::Feed<Storage>>>>,
-    content_storage: Option<hypercore::Storage<Storage>>,
+    // Set once the content key is known, shared by every handle to the drive
+    pub(crate) content: Arc<OnceCell<Arc<RwLock<hypercore::Feed<Storage>>>>>,
+    pub metadata_hub: FeedHub,
+    pub content_hub: FeedHub,
+    pub(crate) storage: Arc<dyn FeedStorage<Storage>>,
+    pub(crate) version: Option<u64>,
+    pub(crate) mounts: Mounts<Storage>,
+    // Held while a write reads the feeds and appends to them, so writes don't interleave
+    pub(crate) writer: Arc<Mutex<()>>,
 }
 
 impl<Storage> Hyperdrive<Storage>
@@ -21,43 +57,232 @@ where
         + Send
         + Sync,
 {
-    pub async fn initialize_content_feed(
-        &mut self,
+    pub(crate) async fn open(
         public_key: hypercore::PublicKey,
-    ) -> anyhow::Result<Arc<RwLock<hypercore::Feed<Storage>>>> {
-        if let Some(storage) = self.content_storage.take() {
-            let feed = hypercore::Feed::builder(public_key, storage)
+        storage: Arc<dyn FeedStorage<Storage>>,
+    ) -> anyhow::Result<Self> {
+        let metadata = hypercore::Feed::builder(public_key, storage.open(&public_key).await?)
+            .build()
+            .await
+            .context("Could not start feed")?;
+
+        let hyperdrive = Hyperdrive {
+            content: Arc::new(OnceCell::new()),
+            metadata: Arc::new(RwLock::new(metadata)),
+            metadata_hub: FeedHub::new(),
+            content_hub: FeedHub::new(),
+            storage,
+            version: None,
+            mounts: Mounts::default(),
+            writer: Arc::new(Mutex::new(())),
+        };
+
+        // Reopen the content feed of a drive downloaded before
+        if let Some(content_key) = hyperdrive.content_public_key().await? {
+            hyperdrive.initialize_content_feed(content_key).await?;
+        }
+        Ok(hyperdrive)
+    }
+
+    /// Creates a drive we can write to, or reopens it if the storage has it already
+    async fn create(
+        keypair: ed25519_dalek::Keypair,
+        storage: Arc<dyn FeedStorage<Storage>>,
+    ) -> anyhow::Result<Self> {
+        let content_keypair = content_keypair(&keypair.secret)?;
+
+        let metadata =
+            hypercore::Feed::builder(keypair.public, storage.open(&keypair.public).await?)
+                .secret_key(keypair.secret)
                 .build()
                 .await
-                .context("Could not start hypercore feed")?;
+                .context("Could not start feed")?;
+        let content = hypercore::Feed::builder(
+            content_keypair.public,
+            storage.open(&content_keypair.public).await?,
+        )
+        .secret_key(content_keypair.secret)
+        .build()
+        .await
+        .context("Could not start hypercore feed")?;
+
+        let hyperdrive = Hyperdrive {
+            content: Arc::new(OnceCell::from(Arc::new(RwLock::new(content)))),
+            metadata: Arc::new(RwLock::new(metadata)),
+            metadata_hub: FeedHub::new(),
+            content_hub: FeedHub::new(),
+            storage,
+            version: None,
+            mounts: Mounts::default(),
+            writer: Arc::new(Mutex::new(())),
+        };
+
+        if hyperdrive.metadata.read().await.is_empty() {
+            let header = HyperdriveHeader::new(content_keypair.public).to_bytes()?;
+            hyperdrive
+                .metadata_hub
+                .append(&hyperdrive.metadata, &header)
+                .await?;
+        }
+        Ok(hyperdrive)
+    }
+
+    /// The content feed, once its key arrived on the first metadata entry
+    pub fn content(&self) -> Option<&Arc<RwLock<hypercore::Feed<Storage>>>> {
+        self.content.get()
+    }
+
+    pub async fn is_writable(&self) -> bool {
+        self.version.is_none() && self.metadata.read().await.secret_key().is_some()
+    }
+
+    /// Whether the metadata and every file on the content feed are stored locally
+    pub async fn is_downloaded(&self) -> anyhow::Result<bool> {
+        let content = match self.content() {
+            Some(content) => content,
+            None => return Ok(false),
+        };
+        {
+            let mut metadata = self.metadata.write().await;
+            let len = metadata.len();
+            if len == 0 || !metadata.has_all(0..len) {
+                return Ok(false);
+            }
+        }
+
+        // The content feed may not know its full length yet
+        let mut end = 0;
+        for node in self.trie().list("").await? {
+            let stat = parse_stat(&node)?;
+            if stat.is_file() {
+                end = end.max(stat.content_blocks()?.end);
+            }
+        }
+        let mut content = content.write().await;
+        Ok(content.len() >= end && content.has_all(0..end))
+    }
+
+    /// The metadata length, which is the version of the drive
+    pub async fn version(&self) -> u64 {
+        self.trie().len().await
+    }
 
-            self.content = Some(Arc::new(RwLock::new(feed)));
+    /// A read-only view of the drive as it was when the metadata had `version` entries
+    pub fn checkout(&self, version: u64) -> Self {
+        self.with_version(Some(version))
+    }
+
+    /// Another handle to the same feeds, seeing up to `version`, or the latest entries
+    pub(crate) fn with_version(&self, version: Option<u64>) -> Self {
+        Self {
+            metadata: self.metadata.clone(),
+            content: self.content.clone(),
+            metadata_hub: self.metadata_hub.clone(),
+            content_hub: self.content_hub.clone(),
+            storage: self.storage.clone(),
+            version,
+            mounts: self.mounts.clone(),
+            writer: self.writer.clone(),
         }
+    }
+
+    /// The header on the first metadata entry, once it is downloaded.
+    /// Fails with a `HeaderError` if the feed is not a hyperdrive.
+    pub async fn header(&self) -> anyhow::Result<Option<HyperdriveHeader>> {
+        let initial_metadata = {
+            let mut metadata = self.metadata.write().await;
+            if !metadata.has(0) {
+                return Ok(None);
+            }
+            metadata.get(0).await?
+        };
 
-        Ok(self.content.as_ref().context("No content to use")?.clone())
+        let initial_metadata = match initial_metadata {
+            Some(initial_metadata) => initial_metadata,
+            None => return Ok(None),
+        };
+        Ok(Some(HyperdriveHeader::parse(&initial_metadata)?))
+    }
+
+    /// The content feed key, kept on the first metadata entry
+    pub async fn content_public_key(&self) -> anyhow::Result<Option<hypercore::PublicKey>> {
+        Ok(self.header().await?.map(|header| header.content))
+    }
+
+    /// Opens the content feed, without a mutable drive so it can be done while others read it
+    pub async fn initialize_content_feed(
+        &self,
+        public_key: hypercore::PublicKey,
+    ) -> anyhow::Result<Arc<RwLock<hypercore::Feed<Storage>>>> {
+        if let Some(content) = self.content() {
+            return Ok(content.clone());
+        }
+
+        let storage = self.storage.open(&public_key).await?;
+        let feed = hypercore::Feed::builder(public_key, storage)
+            .build()
+            .await
+            .context("Could not start hypercore feed")?;
+        // Opened by someone else meanwhile, that one is kept
+        Ok(self
+            .content
+            .get_or_init(|| Arc::new(RwLock::new(feed)))
+            .clone())
     }
 }
 
 pub async fn in_memmory(
     public_key: hypercore::PublicKey,
 ) -> anyhow::Result<Hyperdrive<random_access_memory::RandomAccessMemory>> {
-    let metadata = hypercore::Feed::builder(
-        public_key,
-        hypercore::Storage::new_memory()
-            .await
-            .context("could not page feed memory")?,
-    )
-    .build()
-    .await
-    .context("Could not start feed")?;
+    Hyperdrive::open(public_key, Arc::new(MemoryStorage)).await
+}
 
-    let content_storage = hypercore::Storage::new_memory()
-        .await
-        .context("could not initialize the content storage")?;
+/// Keeps the drive feeds on a folder, reusing what was downloaded before
+pub async fn in_disk(
+    public_key: hypercore::PublicKey,
+    dir: impl AsRef<Path>,
+) -> anyhow::Result<Hyperdrive<random_access_disk::RandomAccessDisk>> {
+    Hyperdrive::open(public_key, Arc::new(DiskStorage::new(dir))).await
+}
+
+/// Creates a new drive in memory, signed with the keypair
+pub async fn create_in_memory(
+    keypair: ed25519_dalek::Keypair,
+) -> anyhow::Result<Hyperdrive<random_access_memory::RandomAccessMemory>> {
+    Hyperdrive::create(keypair, Arc::new(MemoryStorage)).await
+}
 
-    Ok(Hyperdrive {
-        content_storage: Some(content_storage),
-        content: None,
-        metadata: Arc::new(RwLock::new(metadata)),
-    })
+/// Creates a new drive on a folder, or reopens it to keep writing
+pub async fn create_in_disk(
+    keypair: ed25519_dalek::Keypair,
+    dir: impl AsRef<Path>,
+) -> anyhow::Result<Hyperdrive<random_access_disk::RandomAccessDisk>> {
+    Hyperdrive::create(keypair, Arc::new(DiskStorage::new(dir))).await
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use async_std::task;
+
+    #[test]
+    fn written_drives_are_downloaded() {
+        task::block_on(async {
+            let drive = create_in_memory(hypercore::generate_keypair())
+                .await
+                .unwrap();
+            assert!(drive.is_downloaded().await.unwrap());
+            drive.write_file("/file", b"data").await.unwrap();
+            assert!(drive.is_downloaded().await.unwrap());
+        })
+    }
+
+    #[test]
+    fn drives_without_content_are_not_downloaded() {
+        task::block_on(async {
+            let keypair = hypercore::generate_keypair();
+            let drive = in_memmory(keypair.public).await.unwrap();
+            assert!(!drive.is_downloaded().await.unwrap());
+        })
+    }
 }
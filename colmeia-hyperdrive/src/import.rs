@@ -0,0 +1,117 @@
+use anyhow::Context;
+use async_std::{fs, path::PathBuf, prelude::*};
+use filetime::FileTime;
+use std::collections::HashSet;
+use std::path::Path;
+
+use crate::fs::{entry, StatExt, S_IFDIR, S_IFREG};
+use crate::hyperdrive::Hyperdrive;
+use crate::trie;
+
+#[cfg(unix)]
+fn mode(metadata: &std::fs::Metadata) -> u32 {
+    use std::os::unix::fs::PermissionsExt;
+    metadata.permissions().mode() & 0o7777
+}
+
+#[cfg(not(unix))]
+fn mode(metadata: &std::fs::Metadata) -> u32 {
+    if metadata.permissions().readonly() {
+        0o444
+    } else {
+        0o644
+    }
+}
+
+fn mtime(metadata: &std::fs::Metadata) -> u64 {
+    let mtime = FileTime::from_last_modification_time(metadata);
+    mtime.unix_seconds() as u64 * 1000 + u64::from(mtime.nanoseconds()) / 1_000_000
+}
+
+// Like dat, hidden files are not shared. That also leaves our own storage folder out.
+pub(crate) fn is_hidden(name: &str) -> bool {
+    name.starts_with('.')
+}
+
+impl<Storage> Hyperdrive<Storage>
+where
+    Storage: random_access_storage::RandomAccess<Error = Box<dyn std::error::Error + Send + Sync>>
+        + std::fmt::Debug
+        + Send
+        + Sync,
+{
+    /// Adds the files of a folder to the drive, keeping their mode and mtime.
+    /// Files with the same mode, size and mtime as their entry are skipped, and entries
+    /// missing from the folder are removed, so it can run again to pick up changes.
+    pub async fn import(&self, dir: impl AsRef<Path>) -> anyhow::Result<()> {
+        anyhow::ensure!(self.is_writable().await, "drive is not writable");
+        let dir = dir.as_ref();
+
+        let mut seen = HashSet::new();
+        let mut pending = vec![(PathBuf::from(dir), String::new())];
+        while let Some((folder, prefix)) = pending.pop() {
+            let mut entries = fs::read_dir(&folder)
+                .await
+                .with_context(|| format!("could not read {}", folder.display()))?;
+            while let Some(file) = entries.next().await {
+                let file = file?;
+                let name = file.file_name().to_string_lossy().to_string();
+                if is_hidden(&name) {
+                    continue;
+                }
+                let key = format!("{}/{}", prefix, name);
+                let path = file.path();
+                let metadata = fs::symlink_metadata(&path).await?;
+                let current = self.local_stat(&key).await?;
+                seen.insert(trie::normalize(&key).to_string());
+
+                if metadata.file_type().is_symlink() {
+                    let target = fs::read_link(&path).await?;
+                    let target = target.to_string_lossy();
+                    let unchanged = match &current {
+                        Some(stat) => stat.is_symlink() && stat.get_linkname() == target,
+                        None => false,
+                    };
+                    if !unchanged {
+                        log::debug!("importing link {}", key);
+                        self.symlink(&target, &key).await?;
+                    }
+                } else if metadata.is_dir() {
+                    if !matches!(&current, Some(stat) if stat.is_dir()) {
+                        let mut stat = entry(S_IFDIR | mode(&metadata));
+                        stat.set_mtime(mtime(&metadata));
+                        self.put_stat(&key, &stat).await?;
+                    }
+                    pending.push((path, key));
+                } else if metadata.is_file() {
+                    let unchanged = match &current {
+                        Some(stat) => {
+                            stat.get_mode() == S_IFREG | mode(&metadata)
+                                && stat.get_size() == metadata.len()
+                                && stat.get_mtime() == mtime(&metadata)
+                        }
+                        None => false,
+                    };
+                    if unchanged {
+                        continue;
+                    }
+                    log::debug!("importing {}", key);
+                    let file = fs::File::open(&path)
+                        .await
+                        .with_context(|| format!("could not read {}", path.display()))?;
+                    let mut stat = entry(S_IFREG | mode(&metadata));
+                    stat.set_mtime(mtime(&metadata));
+                    self.write_stream_with(&key, file, stat).await?;
+                }
+            }
+        }
+
+        for node in self.trie().list("").await? {
+            if !seen.contains(&node.key) {
+                log::debug!("removing {}", node.key);
+                self.unlink(&node.key).await?;
+            }
+        }
+        Ok(())
+    }
+}
@@ -1,6 +1,21 @@
+mod export;
+mod fs;
+mod header;
+mod history;
 mod hyperdrive;
+mod import;
+mod mount;
 mod network;
 mod schema;
+mod storage;
+mod trie;
 
-pub use hyperdrive::{in_memmory, Hyperdrive};
-pub use network::replicate_hyperdrive;
+pub use fs::StatExt;
+pub use header::{HeaderError, HyperdriveHeader};
+pub use history::{Change, HistoryEntry};
+pub use hyperdrive::{
+    content_keypair, create_in_disk, create_in_memory, in_disk, in_memmory, Hyperdrive,
+};
+pub use network::{replicate_hyperdrive, replicate_hyperdrive_tracked, replicate_hyperdrive_with};
+pub use schema::Stat;
+pub use storage::{DiskStorage, FeedStorage, MemoryStorage};
@@ -0,0 +1,227 @@
+use anyhow::Context;
+use async_std::sync::RwLock;
+use futures::channel::mpsc::{unbounded, UnboundedReceiver, UnboundedSender};
+use std::collections::HashMap;
+use std::sync::{Arc, Mutex, PoisonError};
+use std::time::Duration;
+
+use crate::fs::{entry, S_IFDIR};
+use crate::history::parse_stat;
+use crate::hyperdrive::Hyperdrive;
+use crate::schema::{Mount, Stat};
+use crate::trie;
+
+type SharedDrive<Storage> = Arc<RwLock<Hyperdrive<Storage>>>;
+
+// How long a path waits for the header of a drive mounted on it, before failing
+const MOUNT_TIMEOUT: Duration = Duration::from_secs(10);
+
+/// Drives mounted anywhere under a drive, shared with the drives it mounts,
+/// so each one is opened once and replication can pick up new ones.
+pub(crate) struct Mounts<Storage>
+where
+    Storage: random_access_storage::RandomAccess<Error = Box<dyn std::error::Error + Send + Sync>>
+        + std::fmt::Debug
+        + Send
+        + Sync,
+{
+    drives: Arc<Mutex<HashMap<Vec<u8>, SharedDrive<Storage>>>>,
+    subscribers: Arc<Mutex<Vec<UnboundedSender<SharedDrive<Storage>>>>>,
+}
+
+impl<Storage> Clone for Mounts<Storage>
+where
+    Storage: random_access_storage::RandomAccess<Error = Box<dyn std::error::Error + Send + Sync>>
+        + std::fmt::Debug
+        + Send
+        + Sync,
+{
+    fn clone(&self) -> Self {
+        Self {
+            drives: self.drives.clone(),
+            subscribers: self.subscribers.clone(),
+        }
+    }
+}
+
+impl<Storage> Default for Mounts<Storage>
+where
+    Storage: random_access_storage::RandomAccess<Error = Box<dyn std::error::Error + Send + Sync>>
+        + std::fmt::Debug
+        + Send
+        + Sync,
+{
+    fn default() -> Self {
+        Self {
+            drives: Arc::new(Mutex::new(HashMap::new())),
+            subscribers: Arc::new(Mutex::new(Vec::new())),
+        }
+    }
+}
+
+impl<Storage> Mounts<Storage>
+where
+    Storage: random_access_storage::RandomAccess<Error = Box<dyn std::error::Error + Send + Sync>>
+        + std::fmt::Debug
+        + Send
+        + Sync,
+{
+    /// Receives every mounted drive, the ones opened already and the ones opened later
+    pub fn subscribe(&self) -> UnboundedReceiver<SharedDrive<Storage>> {
+        let (sender, receiver) = unbounded();
+        for drive in self
+            .drives
+            .lock()
+            .unwrap_or_else(PoisonError::into_inner)
+            .values()
+        {
+            let _ = sender.unbounded_send(drive.clone());
+        }
+        self.subscribers
+            .lock()
+            .unwrap_or_else(PoisonError::into_inner)
+            .push(sender);
+        receiver
+    }
+
+    fn get(&self, key: &[u8]) -> Option<SharedDrive<Storage>> {
+        self.drives
+            .lock()
+            .unwrap_or_else(PoisonError::into_inner)
+            .get(key)
+            .cloned()
+    }
+
+    /// Keeps the drive opened first if another one was opened meanwhile
+    fn insert(&self, key: Vec<u8>, drive: SharedDrive<Storage>) -> SharedDrive<Storage> {
+        let mut drives = self.drives.lock().unwrap_or_else(PoisonError::into_inner);
+        if let Some(opened) = drives.get(&key) {
+            return opened.clone();
+        }
+        drives.insert(key, drive.clone());
+        self.subscribers
+            .lock()
+            .unwrap_or_else(PoisonError::into_inner)
+            .retain(|subscriber| subscriber.unbounded_send(drive.clone()).is_ok());
+        drive
+    }
+}
+
+/// Where a path ends up after following the mounts on the way
+pub(crate) struct Resolved<Storage>
+where
+    Storage: random_access_storage::RandomAccess<Error = Box<dyn std::error::Error + Send + Sync>>
+        + std::fmt::Debug
+        + Send
+        + Sync,
+{
+    /// The mounted drive holding the path, or None if it is on this drive
+    pub drive: Option<Hyperdrive<Storage>>,
+    /// The path inside that drive
+    pub path: String,
+    /// The entry of the last mountpoint crossed
+    pub mountpoint: Option<Stat>,
+}
+
+impl<Storage> Hyperdrive<Storage>
+where
+    Storage: random_access_storage::RandomAccess<Error = Box<dyn std::error::Error + Send + Sync>>
+        + std::fmt::Debug
+        + Send
+        + Sync,
+{
+    /// Mounts another drive at the path, like hyperdrive does, as a directory entry with the key
+    pub async fn mount(
+        &self,
+        path: &str,
+        public_key: &hypercore::PublicKey,
+        version: Option<u64>,
+    ) -> anyhow::Result<()> {
+        let mut mount = Mount::new();
+        mount.set_key(public_key.as_bytes().to_vec());
+        if let Some(version) = version {
+            mount.set_version(version);
+        }
+        let mut stat = entry(S_IFDIR | 0o755);
+        stat.set_mount(mount);
+        self.put_stat(path, &stat).await
+    }
+
+    /// Follows the mounts on the path, even on mounted drives.
+    /// The whole path is looked up first, and its parents only when it is not found,
+    /// as what is under a mountpoint is on the mounted drive.
+    pub(crate) async fn resolve(&self, path: &str) -> anyhow::Result<Resolved<Storage>> {
+        let mut components = trie::split(path);
+        let mut resolved = Resolved {
+            drive: None,
+            path: components.join("/"),
+            mountpoint: None,
+        };
+        'drives: loop {
+            let drive = resolved.drive.as_ref().unwrap_or(self);
+            for end in (1..=components.len()).rev() {
+                let node = drive.trie().get(&components[..end].join("/")).await?;
+                let stat = match node {
+                    Some(node) => parse_stat(&node)?,
+                    None => continue,
+                };
+                if !stat.has_mount() {
+                    break 'drives;
+                }
+
+                let mount = stat.get_mount();
+                let version = match mount.get_version() {
+                    0 => None,
+                    version => Some(version),
+                };
+                let mounted = self.open_mount(mount.get_key()).await?;
+                let mounted = mounted.read().await.with_version(version);
+                components = components.split_off(end);
+                resolved = Resolved {
+                    drive: Some(mounted),
+                    path: components.join("/"),
+                    mountpoint: Some(stat),
+                };
+                continue 'drives;
+            }
+            break;
+        }
+        Ok(resolved)
+    }
+
+    /// Opens the mounted drive on the same storage, waiting a while for its content feed key
+    async fn open_mount(&self, key: &[u8]) -> anyhow::Result<SharedDrive<Storage>> {
+        let drive = match self.mounts.get(key) {
+            Some(drive) => drive,
+            None => {
+                let public_key = hypercore::PublicKey::from_bytes(key)
+                    .map_err(|_| anyhow::anyhow!("mount key is not a valid public key"))?;
+                let mut drive = Hyperdrive::open(public_key, self.storage.clone()).await?;
+                drive.mounts = self.mounts.clone();
+                // Announce it before waiting, so replication can download its metadata
+                self.mounts
+                    .insert(key.to_vec(), Arc::new(RwLock::new(drive)))
+            }
+        };
+
+        let (metadata, hub, has_content) = {
+            let drive = drive.read().await;
+            (
+                drive.metadata.clone(),
+                drive.metadata_hub.clone(),
+                drive.content().is_some(),
+            )
+        };
+        // Opened before, the content key may not have arrived then
+        if !has_content {
+            hub.download_block_with_timeout(&metadata, 0, MOUNT_TIMEOUT)
+                .await
+                .context("the mounted drive is not available")?;
+            let opened = drive.read().await;
+            if let Some(content_key) = opened.content_public_key().await? {
+                opened.initialize_content_feed(content_key).await?;
+            }
+        }
+        Ok(drive)
+    }
+}
@@ -1,29 +1,62 @@
+use crate::header::HeaderError;
 use crate::hyperdrive::Hyperdrive;
-use async_std::{sync::RwLock, task};
-use colmeia_hypercore::{Emit, PeeredFeed};
+use async_std::sync::RwLock;
+use colmeia_hypercore::{Emit, FeedEmit, FeedHub, FeedMultiplexer, FeedOptions, RemotePeer};
 use futures::{
+    future,
     io::{AsyncRead, AsyncWrite},
-    FutureExt, StreamExt,
+    stream, StreamExt,
 };
 use hypercore_protocol as proto;
-use std::sync::Arc;
+use std::collections::HashMap;
+use std::sync::{Arc, Mutex};
 
-#[derive(Debug)]
-enum HyperdriveEvents {
-    Client(std::io::Result<proto::Event>),
-    Metadata(Emit),
-    Content(Emit),
+/// Keeps replicating the drive, seeding and downloading new entries
+pub async fn replicate_hyperdrive<C, Storage>(
+    client: proto::Protocol<C, C>,
+    hyperdrive: Arc<RwLock<Hyperdrive<Storage>>>,
+) -> anyhow::Result<()>
+where
+    C: AsyncRead + AsyncWrite + Send + Unpin + Clone + 'static,
+    Storage: random_access_storage::RandomAccess<Error = Box<dyn std::error::Error + Send + Sync>>
+        + std::fmt::Debug
+        + Send
+        + Sync
+        + 'static,
+{
+    let options = FeedOptions {
+        live: true,
+        ..FeedOptions::default()
+    };
+    replicate_hyperdrive_with(client, hyperdrive, options).await
 }
 
-enum JobHolder {
-    Sender(futures::channel::mpsc::UnboundedSender<Emit>),
-    Job(async_std::task::JoinHandle<anyhow::Result<()>>),
+/// Replicates the drive with the given options.
+/// When not live, returns once both feeds are synced and the remote is done with us.
+pub async fn replicate_hyperdrive_with<C, Storage>(
+    client: proto::Protocol<C, C>,
+    hyperdrive: Arc<RwLock<Hyperdrive<Storage>>>,
+    options: FeedOptions,
+) -> anyhow::Result<()>
+where
+    C: AsyncRead + AsyncWrite + Send + Unpin + Clone + 'static,
+    Storage: random_access_storage::RandomAccess<Error = Box<dyn std::error::Error + Send + Sync>>
+        + std::fmt::Debug
+        + Send
+        + Sync
+        + 'static,
+{
+    replicate_hyperdrive_tracked(client, hyperdrive, options, Default::default()).await
 }
 
-pub async fn replicate_hyperdrive<C, Storage>(
-    mut client: proto::Protocol<C, C>,
+/// Same as `replicate_hyperdrive_with`, recording what the remote tells us on `remote`
+pub async fn replicate_hyperdrive_tracked<C, Storage>(
+    client: proto::Protocol<C, C>,
     hyperdrive: Arc<RwLock<Hyperdrive<Storage>>>,
-) where
+    options: FeedOptions,
+    remote: Arc<Mutex<RemotePeer>>,
+) -> anyhow::Result<()>
+where
     C: AsyncRead + AsyncWrite + Send + Unpin + Clone + 'static,
     Storage: random_access_storage::RandomAccess<Error = Box<dyn std::error::Error + Send + Sync>>
         + std::fmt::Debug
@@ -31,119 +64,241 @@ pub async fn replicate_hyperdrive<C, Storage>(
         + Sync
         + 'static,
 {
-    let (metadata_sender, mut metadata_receiver) = futures::channel::mpsc::unbounded();
-    let mut metadata_job = Some(JobHolder::Sender(metadata_sender));
-
-    let (content_sender, mut content_receiver) = futures::channel::mpsc::unbounded();
-    let mut content_job = Some(JobHolder::Sender(content_sender));
-
-    loop {
-        let (event, _, _) = futures::future::select_all(vec![
-            client.loop_next().map(HyperdriveEvents::Client).boxed(),
-            metadata_receiver
-                .select_next_some()
-                .map(HyperdriveEvents::Metadata)
-                .boxed(),
-            content_receiver
-                .select_next_some()
-                .map(HyperdriveEvents::Content)
-                .boxed(),
-        ])
-        .await;
-
-        match event {
-            HyperdriveEvents::Client(Ok(proto::Event::DiscoveryKey(message))) => {
-                if let Some(JobHolder::Sender(_)) = &metadata_job {
-                    let drive = hyperdrive.read().await;
-                    let metadata = drive.metadata.read().await;
-                    let public_key_for_metadata = metadata.public_key().as_bytes();
-                    let metadata_discovery_key =
-                        hypercore_protocol::discovery_key(public_key_for_metadata);
-                    if message == metadata_discovery_key.as_slice()
-                        && client.open(public_key_for_metadata.to_vec()).await.is_err()
-                    {
-                        log::error!("failed to open metadata data exchange");
-                        break;
+    let multiplexer = FeedMultiplexer::new();
+    let (metadata_key, mounted) = {
+        let drive = hyperdrive.read().await;
+        (
+            add_drive(&drive, &multiplexer, options).await,
+            drive.mounts.subscribe(),
+        )
+    };
+
+    // The multiplexer never waits on the drive, it is held instead until the emits up to
+    // the end of the metadata are handled, as they may add the content feed
+    let (sender, receiver) = futures::channel::mpsc::unbounded();
+    let hold = multiplexer.hold();
+    let replication = multiplexer.replicate_tracked(client, sender, remote);
+
+    let content = async {
+        let mut hold = Some(hold);
+        // Mounted drives are replicated as they are opened
+        let mut drives = HashMap::new();
+        drives.insert(metadata_key.clone(), hyperdrive.clone());
+
+        let emits = receiver
+            .map(Event::Emit)
+            .chain(stream::once(future::ready(Event::Ended)));
+        let mut events = stream::select(emits, mounted.map(Event::Mounted));
+        while let Some(event) = events.next().await {
+            match event {
+                Event::Emit(FeedEmit {
+                    discovery_key,
+                    emit: Emit::OnData,
+                }) => {
+                    let drive = match drives.get(&discovery_key) {
+                        Some(drive) => drive,
+                        None => continue,
                     };
+                    // The first metadata entry has the content feed key
+                    if let Err(error) = initialize_content_feed(drive, &multiplexer, options).await
+                    {
+                        // No peer will send a different header for the same key
+                        if discovery_key == metadata_key && error.is::<HeaderError>() {
+                            return Err(error);
+                        }
+                        log::error!("failed to open content feed: {:?}", error);
+                    }
                 }
-            }
-            HyperdriveEvents::Client(Ok(proto::Event::Channel(channel))) => {
-                // TODO don't rely on channel event oerder - check the keys to see if they match
-                if let Some(JobHolder::Sender(sender)) = metadata_job.take() {
-                    log::debug!("initializing metadata feed");
-                    let feed = hyperdrive.read().await.metadata.clone();
-                    metadata_job = Some(JobHolder::Job(task::spawn(async {
-                        let mut peer = PeeredFeed {
-                            channel,
-                            feed,
-                            remote_length: 0,
-                        };
-                        peer.replicate(sender).await
-                    })));
-                    continue;
+                Event::Emit(FeedEmit {
+                    discovery_key,
+                    emit: Emit::Ended,
+                }) if discovery_key == metadata_key => {
+                    hold.take();
                 }
-                if let Some(JobHolder::Sender(sender)) = content_job.take() {
-                    log::debug!("initializing content feed");
-                    let feed = hyperdrive.read().await.content.clone();
-                    if let Some(feed) = feed {
-                        content_job = Some(JobHolder::Job(task::spawn(async {
-                            let mut peer = PeeredFeed {
-                                channel,
-                                feed,
-                                remote_length: 0,
-                            };
-                            peer.replicate(sender).await
-                        })));
-                    }
-                    continue;
+                Event::Emit(_) => {}
+                Event::Mounted(drive) => {
+                    let key = add_drive(&*drive.read().await, &multiplexer, options).await;
+                    drives.insert(key, drive);
                 }
+                Event::Ended => break,
             }
-            HyperdriveEvents::Client(Err(_)) => {
-                break;
+        }
+        Ok(())
+    };
+
+    future::try_join(replication, content).await?;
+    Ok(())
+}
+
+enum Event<Storage>
+where
+    Storage: random_access_storage::RandomAccess<Error = Box<dyn std::error::Error + Send + Sync>>
+        + std::fmt::Debug
+        + Send
+        + Sync,
+{
+    Emit(FeedEmit),
+    Mounted(Arc<RwLock<Hyperdrive<Storage>>>),
+    Ended,
+}
+
+/// Adds the feeds of the drive not replicated yet, returning the metadata discovery key.
+/// Only the content feed is sparse, as the entries are needed to find anything on the drive,
+/// the same as `sparseMetadata: false` on hyperdrive.
+async fn add_drive<Storage>(
+    drive: &Hyperdrive<Storage>,
+    multiplexer: &FeedMultiplexer<Storage>,
+    options: FeedOptions,
+) -> Vec<u8>
+where
+    Storage: random_access_storage::RandomAccess<Error = Box<dyn std::error::Error + Send + Sync>>
+        + std::fmt::Debug
+        + Send
+        + Sync
+        + 'static,
+{
+    if let Some(content) = drive.content() {
+        add_feed(content, &drive.content_hub, multiplexer, options).await;
+    }
+    let metadata_options = FeedOptions {
+        sparse: false,
+        ..options
+    };
+    add_feed(
+        &drive.metadata,
+        &drive.metadata_hub,
+        multiplexer,
+        metadata_options,
+    )
+    .await
+}
+
+async fn add_feed<Storage>(
+    feed: &Arc<RwLock<hypercore::Feed<Storage>>>,
+    hub: &FeedHub,
+    multiplexer: &FeedMultiplexer<Storage>,
+    options: FeedOptions,
+) -> Vec<u8>
+where
+    Storage: random_access_storage::RandomAccess<Error = Box<dyn std::error::Error + Send + Sync>>
+        + std::fmt::Debug
+        + Send
+        + Sync
+        + 'static,
+{
+    let discovery_key = proto::discovery_key(feed.read().await.public_key().as_bytes());
+    if multiplexer.contains(&discovery_key) {
+        return discovery_key;
+    }
+    multiplexer.add(feed.clone(), hub.clone(), options).await
+}
+
+async fn initialize_content_feed<Storage>(
+    hyperdrive: &Arc<RwLock<Hyperdrive<Storage>>>,
+    multiplexer: &FeedMultiplexer<Storage>,
+    options: FeedOptions,
+) -> anyhow::Result<()>
+where
+    Storage: random_access_storage::RandomAccess<Error = Box<dyn std::error::Error + Send + Sync>>
+        + std::fmt::Debug
+        + Send
+        + Sync
+        + 'static,
+{
+    let driver = hyperdrive.read().await;
+    if let Some(content) = driver.content() {
+        add_feed(content, &driver.content_hub, multiplexer, options).await;
+        return Ok(());
+    }
+    let public_key = match driver.content_public_key().await? {
+        Some(public_key) => public_key,
+        None => return Ok(()),
+    };
+    let content = driver.initialize_content_feed(public_key).await?;
+    add_feed(&content, &driver.content_hub, multiplexer, options).await;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::hyperdrive::{create_in_memory, in_memmory};
+    use async_std::task;
+    use std::time::{Duration, Instant};
+
+    #[cfg(unix)]
+    #[test]
+    fn sparse_drives_download_every_entry() {
+        task::block_on(async {
+            let keypair = hypercore::generate_keypair();
+            let public_key = keypair.public;
+            let writer = create_in_memory(keypair).await.unwrap();
+            writer.write_file("/file", b"data").await.unwrap();
+            let length = writer.metadata.read().await.len();
+            let reader = Arc::new(RwLock::new(in_memmory(public_key).await.unwrap()));
+
+            let options = FeedOptions {
+                live: true,
+                sparse: true,
+                ..FeedOptions::default()
+            };
+            let (seeder_stream, leecher_stream) =
+                async_std::os::unix::net::UnixStream::pair().unwrap();
+            let seeding = task::spawn(replicate_hyperdrive_with(
+                proto::ProtocolBuilder::new(false).connect(seeder_stream),
+                Arc::new(RwLock::new(writer)),
+                options,
+            ));
+            let leeching = task::spawn(replicate_hyperdrive_with(
+                proto::ProtocolBuilder::new(true).connect(leecher_stream),
+                reader.clone(),
+                options,
+            ));
+
+            // Nothing is asked, the entries still arrive
+            let metadata = reader.read().await.metadata.clone();
+            let started = Instant::now();
+            while !metadata.write().await.has_all(0..length) {
+                assert!(started.elapsed() < Duration::from_secs(5), "no entries");
+                task::sleep(Duration::from_millis(10)).await;
             }
-            // TODO listen to metadata ondata evnt to initialize content feed
-            HyperdriveEvents::Metadata(_) => {
-                if let Some(JobHolder::Sender(_)) = &content_job {
-                    // Initialize the content feed if we have no job started
-                    let initial_metadata = {
-                        let driver = hyperdrive.read().await;
-                        let mut metadata = driver.metadata.write().await;
-                        metadata.get(0).await
-                    };
-                    if let Ok(Some(initial_metadata)) = initial_metadata {
-                        let content = match protobuf::parse_from_bytes::<crate::schema::Index>(
-                            &initial_metadata,
-                        ) {
-                            Ok(e) => e,
-                            _ => continue,
-                        };
-
-                        let public_key =
-                            match hypercore::PublicKey::from_bytes(content.get_content()) {
-                                Ok(e) => e,
-                                _ => {
-                                    log::error!(
-                                        "feed content first entry is not a valid public key",
-                                    );
-                                    break;
-                                }
-                            };
-
-                        if hyperdrive
-                            .write()
-                            .await
-                            .initialize_content_feed(public_key)
-                            .await
-                            .is_ok()
-                            && client.open(public_key.as_bytes().to_vec()).await.is_err()
-                        {
-                            log::error!("failed to open metadata data exchange");
-                            break;
-                        };
-                    }
-                }
+            assert!(reader.read().await.stat("/file").await.unwrap().is_some());
+            leeching.cancel().await;
+            seeding.cancel().await;
+        })
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn content_feeds_open_while_the_drive_is_read() {
+        task::block_on(async {
+            let keypair = hypercore::generate_keypair();
+            let public_key = keypair.public;
+            let writer = create_in_memory(keypair).await.unwrap();
+            writer.write_file("/file", b"data").await.unwrap();
+            let reader = Arc::new(RwLock::new(in_memmory(public_key).await.unwrap()));
+            let reading = reader.read().await;
+
+            let (seeder_stream, leecher_stream) =
+                async_std::os::unix::net::UnixStream::pair().unwrap();
+            let seeding = task::spawn(replicate_hyperdrive(
+                proto::ProtocolBuilder::new(false).connect(seeder_stream),
+                Arc::new(RwLock::new(writer)),
+            ));
+            let leeching = task::spawn(replicate_hyperdrive(
+                proto::ProtocolBuilder::new(true).connect(leecher_stream),
+                reader.clone(),
+            ));
+
+            let started = Instant::now();
+            while reading.content().is_none() {
+                assert!(started.elapsed() < Duration::from_secs(5), "no content");
+                task::sleep(Duration::from_millis(10)).await;
             }
-            _ => {}
-        };
+            assert_eq!(reading.read_file("/file").await.unwrap(), b"data");
+            drop(reading);
+            leeching.cancel().await;
+            seeding.cancel().await;
+        })
     }
 }
@@ -23,6 +23,312 @@
 /// of protobuf runtime.
 // const _PROTOBUF_VERSION_CHECK: () = ::protobuf::VERSION_2_16_2;
 
+#[derive(PartialEq, Clone, Default)]
+pub struct Header {
+    // message fields
+    field_type: ::protobuf::SingularField<::std::string::String>,
+    metadata: ::protobuf::SingularField<::std::vec::Vec<u8>>,
+    subtype: ::protobuf::SingularField<::std::string::String>,
+    // special fields
+    pub unknown_fields: ::protobuf::UnknownFields,
+    pub cached_size: ::protobuf::CachedSize,
+}
+
+impl<'a> ::std::default::Default for &'a Header {
+    fn default() -> &'a Header {
+        <Header as ::protobuf::Message>::default_instance()
+    }
+}
+
+impl Header {
+    pub fn new() -> Header {
+        ::std::default::Default::default()
+    }
+
+    // required string type = 1;
+
+    pub fn get_field_type(&self) -> &str {
+        match self.field_type.as_ref() {
+            Some(v) => &v,
+            None => "",
+        }
+    }
+    pub fn clear_field_type(&mut self) {
+        self.field_type.clear();
+    }
+
+    pub fn has_field_type(&self) -> bool {
+        self.field_type.is_some()
+    }
+
+    // Param is passed by value, moved
+    pub fn set_field_type(&mut self, v: ::std::string::String) {
+        self.field_type = ::protobuf::SingularField::some(v);
+    }
+
+    // Mutable pointer to the field.
+    // If field is not initialized, it is initialized with default value first.
+    pub fn mut_field_type(&mut self) -> &mut ::std::string::String {
+        if self.field_type.is_none() {
+            self.field_type.set_default();
+        }
+        self.field_type.as_mut().unwrap()
+    }
+
+    // Take field
+    pub fn take_field_type(&mut self) -> ::std::string::String {
+        self.field_type
+            .take()
+            .unwrap_or_else(|| ::std::string::String::new())
+    }
+
+    // optional bytes metadata = 2;
+
+    pub fn get_metadata(&self) -> &[u8] {
+        match self.metadata.as_ref() {
+            Some(v) => &v,
+            None => &[],
+        }
+    }
+    pub fn clear_metadata(&mut self) {
+        self.metadata.clear();
+    }
+
+    pub fn has_metadata(&self) -> bool {
+        self.metadata.is_some()
+    }
+
+    // Param is passed by value, moved
+    pub fn set_metadata(&mut self, v: ::std::vec::Vec<u8>) {
+        self.metadata = ::protobuf::SingularField::some(v);
+    }
+
+    // Mutable pointer to the field.
+    // If field is not initialized, it is initialized with default value first.
+    pub fn mut_metadata(&mut self) -> &mut ::std::vec::Vec<u8> {
+        if self.metadata.is_none() {
+            self.metadata.set_default();
+        }
+        self.metadata.as_mut().unwrap()
+    }
+
+    // Take field
+    pub fn take_metadata(&mut self) -> ::std::vec::Vec<u8> {
+        self.metadata
+            .take()
+            .unwrap_or_else(|| ::std::vec::Vec::new())
+    }
+
+    // optional string subtype = 3;
+
+    pub fn get_subtype(&self) -> &str {
+        match self.subtype.as_ref() {
+            Some(v) => &v,
+            None => "",
+        }
+    }
+    pub fn clear_subtype(&mut self) {
+        self.subtype.clear();
+    }
+
+    pub fn has_subtype(&self) -> bool {
+        self.subtype.is_some()
+    }
+
+    // Param is passed by value, moved
+    pub fn set_subtype(&mut self, v: ::std::string::String) {
+        self.subtype = ::protobuf::SingularField::some(v);
+    }
+
+    // Mutable pointer to the field.
+    // If field is not initialized, it is initialized with default value first.
+    pub fn mut_subtype(&mut self) -> &mut ::std::string::String {
+        if self.subtype.is_none() {
+            self.subtype.set_default();
+        }
+        self.subtype.as_mut().unwrap()
+    }
+
+    // Take field
+    pub fn take_subtype(&mut self) -> ::std::string::String {
+        self.subtype
+            .take()
+            .unwrap_or_else(|| ::std::string::String::new())
+    }
+}
+
+impl ::protobuf::Message for Header {
+    fn is_initialized(&self) -> bool {
+        if self.field_type.is_none() {
+            return false;
+        }
+        true
+    }
+
+    fn merge_from(
+        &mut self,
+        is: &mut ::protobuf::CodedInputStream<'_>,
+    ) -> ::protobuf::ProtobufResult<()> {
+        while !is.eof()? {
+            let (field_number, wire_type) = is.read_tag_unpack()?;
+            match field_number {
+                1 => {
+                    ::protobuf::rt::read_singular_string_into(wire_type, is, &mut self.field_type)?;
+                }
+                2 => {
+                    ::protobuf::rt::read_singular_bytes_into(wire_type, is, &mut self.metadata)?;
+                }
+                3 => {
+                    ::protobuf::rt::read_singular_string_into(wire_type, is, &mut self.subtype)?;
+                }
+                _ => {
+                    ::protobuf::rt::read_unknown_or_skip_group(
+                        field_number,
+                        wire_type,
+                        is,
+                        self.mut_unknown_fields(),
+                    )?;
+                }
+            };
+        }
+        ::std::result::Result::Ok(())
+    }
+
+    // Compute sizes of nested messages
+    #[allow(unused_variables)]
+    fn compute_size(&self) -> u32 {
+        let mut my_size = 0;
+        if let Some(ref v) = self.field_type.as_ref() {
+            my_size += ::protobuf::rt::string_size(1, &v);
+        }
+        if let Some(ref v) = self.metadata.as_ref() {
+            my_size += ::protobuf::rt::bytes_size(2, &v);
+        }
+        if let Some(ref v) = self.subtype.as_ref() {
+            my_size += ::protobuf::rt::string_size(3, &v);
+        }
+        my_size += ::protobuf::rt::unknown_fields_size(self.get_unknown_fields());
+        self.cached_size.set(my_size);
+        my_size
+    }
+
+    fn write_to_with_cached_sizes(
+        &self,
+        os: &mut ::protobuf::CodedOutputStream<'_>,
+    ) -> ::protobuf::ProtobufResult<()> {
+        if let Some(ref v) = self.field_type.as_ref() {
+            os.write_string(1, &v)?;
+        }
+        if let Some(ref v) = self.metadata.as_ref() {
+            os.write_bytes(2, &v)?;
+        }
+        if let Some(ref v) = self.subtype.as_ref() {
+            os.write_string(3, &v)?;
+        }
+        os.write_unknown_fields(self.get_unknown_fields())?;
+        ::std::result::Result::Ok(())
+    }
+
+    fn get_cached_size(&self) -> u32 {
+        self.cached_size.get()
+    }
+
+    fn get_unknown_fields(&self) -> &::protobuf::UnknownFields {
+        &self.unknown_fields
+    }
+
+    fn mut_unknown_fields(&mut self) -> &mut ::protobuf::UnknownFields {
+        &mut self.unknown_fields
+    }
+
+    fn as_any(&self) -> &dyn (::std::any::Any) {
+        self as &dyn (::std::any::Any)
+    }
+    fn as_any_mut(&mut self) -> &mut dyn (::std::any::Any) {
+        self as &mut dyn (::std::any::Any)
+    }
+    fn into_any(self: ::std::boxed::Box<Self>) -> ::std::boxed::Box<dyn (::std::any::Any)> {
+        self
+    }
+
+    fn descriptor(&self) -> &'static ::protobuf::reflect::MessageDescriptor {
+        Self::descriptor_static()
+    }
+
+    fn new() -> Header {
+        Header::new()
+    }
+
+    fn descriptor_static() -> &'static ::protobuf::reflect::MessageDescriptor {
+        static descriptor: ::protobuf::rt::LazyV2<::protobuf::reflect::MessageDescriptor> =
+            ::protobuf::rt::LazyV2::INIT;
+        descriptor.get(|| {
+            let mut fields = ::std::vec::Vec::new();
+            fields.push(
+                ::protobuf::reflect::accessor::make_singular_field_accessor::<
+                    _,
+                    ::protobuf::types::ProtobufTypeString,
+                >(
+                    "type",
+                    |m: &Header| &m.field_type,
+                    |m: &mut Header| &mut m.field_type,
+                ),
+            );
+            fields.push(
+                ::protobuf::reflect::accessor::make_singular_field_accessor::<
+                    _,
+                    ::protobuf::types::ProtobufTypeBytes,
+                >(
+                    "metadata",
+                    |m: &Header| &m.metadata,
+                    |m: &mut Header| &mut m.metadata,
+                ),
+            );
+            fields.push(
+                ::protobuf::reflect::accessor::make_singular_field_accessor::<
+                    _,
+                    ::protobuf::types::ProtobufTypeString,
+                >(
+                    "subtype",
+                    |m: &Header| &m.subtype,
+                    |m: &mut Header| &mut m.subtype,
+                ),
+            );
+            ::protobuf::reflect::MessageDescriptor::new_pb_name::<Header>(
+                "Header",
+                fields,
+                file_descriptor_proto(),
+            )
+        })
+    }
+
+    fn default_instance() -> &'static Header {
+        static instance: ::protobuf::rt::LazyV2<Header> = ::protobuf::rt::LazyV2::INIT;
+        instance.get(Header::new)
+    }
+}
+
+impl ::protobuf::Clear for Header {
+    fn clear(&mut self) {
+        self.field_type.clear();
+        self.metadata.clear();
+        self.subtype.clear();
+        self.unknown_fields.clear();
+    }
+}
+
+impl ::std::fmt::Debug for Header {
+    fn fmt(&self, f: &mut ::std::fmt::Formatter<'_>) -> ::std::fmt::Result {
+        ::protobuf::text_format::fmt(self, f)
+    }
+}
+
+impl ::protobuf::reflect::ProtobufValue for Header {
+    fn as_ref(&self) -> ::protobuf::reflect::ReflectValueRef {
+        ::protobuf::reflect::ReflectValueRef::Message(self)
+    }
+}
+
 #[derive(PartialEq, Clone, Default)]
 pub struct Index {
     // message fields
@@ -272,36 +578,361 @@ impl ::protobuf::reflect::ProtobufValue for Index {
 }
 
 #[derive(PartialEq, Clone, Default)]
-pub struct Stat {
+pub struct Mount {
     // message fields
-    mode: ::std::option::Option<u32>,
-    uid: ::std::option::Option<u32>,
-    gid: ::std::option::Option<u32>,
-    size: ::std::option::Option<u64>,
-    blocks: ::std::option::Option<u64>,
-    offset: ::std::option::Option<u64>,
-    byteOffset: ::std::option::Option<u64>,
-    mtime: ::std::option::Option<u64>,
-    ctime: ::std::option::Option<u64>,
+    key: ::protobuf::SingularField<::std::vec::Vec<u8>>,
+    version: ::std::option::Option<u64>,
+    hash: ::protobuf::SingularField<::std::vec::Vec<u8>>,
+    hypercore: ::std::option::Option<bool>,
     // special fields
     pub unknown_fields: ::protobuf::UnknownFields,
     pub cached_size: ::protobuf::CachedSize,
 }
 
-impl<'a> ::std::default::Default for &'a Stat {
-    fn default() -> &'a Stat {
-        <Stat as ::protobuf::Message>::default_instance()
+impl<'a> ::std::default::Default for &'a Mount {
+    fn default() -> &'a Mount {
+        <Mount as ::protobuf::Message>::default_instance()
     }
 }
 
-impl Stat {
-    pub fn new() -> Stat {
+impl Mount {
+    pub fn new() -> Mount {
         ::std::default::Default::default()
     }
 
-    // required uint32 mode = 1;
+    // required bytes key = 1;
 
-    pub fn get_mode(&self) -> u32 {
+    pub fn get_key(&self) -> &[u8] {
+        match self.key.as_ref() {
+            Some(v) => &v,
+            None => &[],
+        }
+    }
+    pub fn clear_key(&mut self) {
+        self.key.clear();
+    }
+
+    pub fn has_key(&self) -> bool {
+        self.key.is_some()
+    }
+
+    // Param is passed by value, moved
+    pub fn set_key(&mut self, v: ::std::vec::Vec<u8>) {
+        self.key = ::protobuf::SingularField::some(v);
+    }
+
+    // Mutable pointer to the field.
+    // If field is not initialized, it is initialized with default value first.
+    pub fn mut_key(&mut self) -> &mut ::std::vec::Vec<u8> {
+        if self.key.is_none() {
+            self.key.set_default();
+        }
+        self.key.as_mut().unwrap()
+    }
+
+    // Take field
+    pub fn take_key(&mut self) -> ::std::vec::Vec<u8> {
+        self.key.take().unwrap_or_else(|| ::std::vec::Vec::new())
+    }
+
+    // optional uint64 version = 2;
+
+    pub fn get_version(&self) -> u64 {
+        self.version.unwrap_or(0)
+    }
+    pub fn clear_version(&mut self) {
+        self.version = ::std::option::Option::None;
+    }
+
+    pub fn has_version(&self) -> bool {
+        self.version.is_some()
+    }
+
+    // Param is passed by value, moved
+    pub fn set_version(&mut self, v: u64) {
+        self.version = ::std::option::Option::Some(v);
+    }
+
+    // optional bytes hash = 3;
+
+    pub fn get_hash(&self) -> &[u8] {
+        match self.hash.as_ref() {
+            Some(v) => &v,
+            None => &[],
+        }
+    }
+    pub fn clear_hash(&mut self) {
+        self.hash.clear();
+    }
+
+    pub fn has_hash(&self) -> bool {
+        self.hash.is_some()
+    }
+
+    // Param is passed by value, moved
+    pub fn set_hash(&mut self, v: ::std::vec::Vec<u8>) {
+        self.hash = ::protobuf::SingularField::some(v);
+    }
+
+    // Mutable pointer to the field.
+    // If field is not initialized, it is initialized with default value first.
+    pub fn mut_hash(&mut self) -> &mut ::std::vec::Vec<u8> {
+        if self.hash.is_none() {
+            self.hash.set_default();
+        }
+        self.hash.as_mut().unwrap()
+    }
+
+    // Take field
+    pub fn take_hash(&mut self) -> ::std::vec::Vec<u8> {
+        self.hash.take().unwrap_or_else(|| ::std::vec::Vec::new())
+    }
+
+    // optional bool hypercore = 4;
+
+    pub fn get_hypercore(&self) -> bool {
+        self.hypercore.unwrap_or(false)
+    }
+    pub fn clear_hypercore(&mut self) {
+        self.hypercore = ::std::option::Option::None;
+    }
+
+    pub fn has_hypercore(&self) -> bool {
+        self.hypercore.is_some()
+    }
+
+    // Param is passed by value, moved
+    pub fn set_hypercore(&mut self, v: bool) {
+        self.hypercore = ::std::option::Option::Some(v);
+    }
+}
+
+impl ::protobuf::Message for Mount {
+    fn is_initialized(&self) -> bool {
+        if self.key.is_none() {
+            return false;
+        }
+        true
+    }
+
+    fn merge_from(
+        &mut self,
+        is: &mut ::protobuf::CodedInputStream<'_>,
+    ) -> ::protobuf::ProtobufResult<()> {
+        while !is.eof()? {
+            let (field_number, wire_type) = is.read_tag_unpack()?;
+            match field_number {
+                1 => {
+                    ::protobuf::rt::read_singular_bytes_into(wire_type, is, &mut self.key)?;
+                }
+                2 => {
+                    if wire_type != ::protobuf::wire_format::WireTypeVarint {
+                        return ::std::result::Result::Err(::protobuf::rt::unexpected_wire_type(
+                            wire_type,
+                        ));
+                    }
+                    let tmp = is.read_uint64()?;
+                    self.version = ::std::option::Option::Some(tmp);
+                }
+                3 => {
+                    ::protobuf::rt::read_singular_bytes_into(wire_type, is, &mut self.hash)?;
+                }
+                4 => {
+                    if wire_type != ::protobuf::wire_format::WireTypeVarint {
+                        return ::std::result::Result::Err(::protobuf::rt::unexpected_wire_type(
+                            wire_type,
+                        ));
+                    }
+                    let tmp = is.read_bool()?;
+                    self.hypercore = ::std::option::Option::Some(tmp);
+                }
+                _ => {
+                    ::protobuf::rt::read_unknown_or_skip_group(
+                        field_number,
+                        wire_type,
+                        is,
+                        self.mut_unknown_fields(),
+                    )?;
+                }
+            };
+        }
+        ::std::result::Result::Ok(())
+    }
+
+    // Compute sizes of nested messages
+    #[allow(unused_variables)]
+    fn compute_size(&self) -> u32 {
+        let mut my_size = 0;
+        if let Some(ref v) = self.key.as_ref() {
+            my_size += ::protobuf::rt::bytes_size(1, &v);
+        }
+        if let Some(v) = self.version {
+            my_size += ::protobuf::rt::value_size(2, v, ::protobuf::wire_format::WireTypeVarint);
+        }
+        if let Some(ref v) = self.hash.as_ref() {
+            my_size += ::protobuf::rt::bytes_size(3, &v);
+        }
+        if let Some(v) = self.hypercore {
+            my_size += 2;
+        }
+        my_size += ::protobuf::rt::unknown_fields_size(self.get_unknown_fields());
+        self.cached_size.set(my_size);
+        my_size
+    }
+
+    fn write_to_with_cached_sizes(
+        &self,
+        os: &mut ::protobuf::CodedOutputStream<'_>,
+    ) -> ::protobuf::ProtobufResult<()> {
+        if let Some(ref v) = self.key.as_ref() {
+            os.write_bytes(1, &v)?;
+        }
+        if let Some(v) = self.version {
+            os.write_uint64(2, v)?;
+        }
+        if let Some(ref v) = self.hash.as_ref() {
+            os.write_bytes(3, &v)?;
+        }
+        if let Some(v) = self.hypercore {
+            os.write_bool(4, v)?;
+        }
+        os.write_unknown_fields(self.get_unknown_fields())?;
+        ::std::result::Result::Ok(())
+    }
+
+    fn get_cached_size(&self) -> u32 {
+        self.cached_size.get()
+    }
+
+    fn get_unknown_fields(&self) -> &::protobuf::UnknownFields {
+        &self.unknown_fields
+    }
+
+    fn mut_unknown_fields(&mut self) -> &mut ::protobuf::UnknownFields {
+        &mut self.unknown_fields
+    }
+
+    fn as_any(&self) -> &dyn (::std::any::Any) {
+        self as &dyn (::std::any::Any)
+    }
+    fn as_any_mut(&mut self) -> &mut dyn (::std::any::Any) {
+        self as &mut dyn (::std::any::Any)
+    }
+    fn into_any(self: ::std::boxed::Box<Self>) -> ::std::boxed::Box<dyn (::std::any::Any)> {
+        self
+    }
+
+    fn descriptor(&self) -> &'static ::protobuf::reflect::MessageDescriptor {
+        Self::descriptor_static()
+    }
+
+    fn new() -> Mount {
+        Mount::new()
+    }
+
+    fn descriptor_static() -> &'static ::protobuf::reflect::MessageDescriptor {
+        static descriptor: ::protobuf::rt::LazyV2<::protobuf::reflect::MessageDescriptor> =
+            ::protobuf::rt::LazyV2::INIT;
+        descriptor.get(|| {
+            let mut fields = ::std::vec::Vec::new();
+            fields.push(
+                ::protobuf::reflect::accessor::make_singular_field_accessor::<
+                    _,
+                    ::protobuf::types::ProtobufTypeBytes,
+                >("key", |m: &Mount| &m.key, |m: &mut Mount| &mut m.key),
+            );
+            fields.push(::protobuf::reflect::accessor::make_option_accessor::<
+                _,
+                ::protobuf::types::ProtobufTypeUint64,
+            >(
+                "version",
+                |m: &Mount| &m.version,
+                |m: &mut Mount| &mut m.version,
+            ));
+            fields.push(
+                ::protobuf::reflect::accessor::make_singular_field_accessor::<
+                    _,
+                    ::protobuf::types::ProtobufTypeBytes,
+                >("hash", |m: &Mount| &m.hash, |m: &mut Mount| &mut m.hash),
+            );
+            fields.push(::protobuf::reflect::accessor::make_option_accessor::<
+                _,
+                ::protobuf::types::ProtobufTypeBool,
+            >(
+                "hypercore",
+                |m: &Mount| &m.hypercore,
+                |m: &mut Mount| &mut m.hypercore,
+            ));
+            ::protobuf::reflect::MessageDescriptor::new_pb_name::<Mount>(
+                "Mount",
+                fields,
+                file_descriptor_proto(),
+            )
+        })
+    }
+
+    fn default_instance() -> &'static Mount {
+        static instance: ::protobuf::rt::LazyV2<Mount> = ::protobuf::rt::LazyV2::INIT;
+        instance.get(Mount::new)
+    }
+}
+
+impl ::protobuf::Clear for Mount {
+    fn clear(&mut self) {
+        self.key.clear();
+        self.version = ::std::option::Option::None;
+        self.hash.clear();
+        self.hypercore = ::std::option::Option::None;
+        self.unknown_fields.clear();
+    }
+}
+
+impl ::std::fmt::Debug for Mount {
+    fn fmt(&self, f: &mut ::std::fmt::Formatter<'_>) -> ::std::fmt::Result {
+        ::protobuf::text_format::fmt(self, f)
+    }
+}
+
+impl ::protobuf::reflect::ProtobufValue for Mount {
+    fn as_ref(&self) -> ::protobuf::reflect::ReflectValueRef {
+        ::protobuf::reflect::ReflectValueRef::Message(self)
+    }
+}
+
+#[derive(PartialEq, Clone, Default)]
+pub struct Stat {
+    // message fields
+    mode: ::std::option::Option<u32>,
+    uid: ::std::option::Option<u32>,
+    gid: ::std::option::Option<u32>,
+    size: ::std::option::Option<u64>,
+    blocks: ::std::option::Option<u64>,
+    offset: ::std::option::Option<u64>,
+    byteOffset: ::std::option::Option<u64>,
+    mtime: ::std::option::Option<u64>,
+    ctime: ::std::option::Option<u64>,
+    linkname: ::protobuf::SingularField<::std::string::String>,
+    pub mount: ::protobuf::SingularPtrField<Mount>,
+    pub metadata: ::std::collections::HashMap<::std::string::String, ::std::vec::Vec<u8>>,
+    // special fields
+    pub unknown_fields: ::protobuf::UnknownFields,
+    pub cached_size: ::protobuf::CachedSize,
+}
+
+impl<'a> ::std::default::Default for &'a Stat {
+    fn default() -> &'a Stat {
+        <Stat as ::protobuf::Message>::default_instance()
+    }
+}
+
+impl Stat {
+    pub fn new() -> Stat {
+        ::std::default::Default::default()
+    }
+
+    // required uint32 mode = 1;
+
+    pub fn get_mode(&self) -> u32 {
         self.mode.unwrap_or(0)
     }
     pub fn clear_mode(&mut self) {
@@ -389,82 +1020,742 @@ impl Stat {
         self.blocks = ::std::option::Option::Some(v);
     }
 
-    // optional uint64 offset = 6;
+    // optional uint64 offset = 6;
+
+    pub fn get_offset(&self) -> u64 {
+        self.offset.unwrap_or(0)
+    }
+    pub fn clear_offset(&mut self) {
+        self.offset = ::std::option::Option::None;
+    }
+
+    pub fn has_offset(&self) -> bool {
+        self.offset.is_some()
+    }
+
+    // Param is passed by value, moved
+    pub fn set_offset(&mut self, v: u64) {
+        self.offset = ::std::option::Option::Some(v);
+    }
+
+    // optional uint64 byteOffset = 7;
+
+    pub fn get_byteOffset(&self) -> u64 {
+        self.byteOffset.unwrap_or(0)
+    }
+    pub fn clear_byteOffset(&mut self) {
+        self.byteOffset = ::std::option::Option::None;
+    }
+
+    pub fn has_byteOffset(&self) -> bool {
+        self.byteOffset.is_some()
+    }
+
+    // Param is passed by value, moved
+    pub fn set_byteOffset(&mut self, v: u64) {
+        self.byteOffset = ::std::option::Option::Some(v);
+    }
+
+    // optional uint64 mtime = 8;
+
+    pub fn get_mtime(&self) -> u64 {
+        self.mtime.unwrap_or(0)
+    }
+    pub fn clear_mtime(&mut self) {
+        self.mtime = ::std::option::Option::None;
+    }
+
+    pub fn has_mtime(&self) -> bool {
+        self.mtime.is_some()
+    }
+
+    // Param is passed by value, moved
+    pub fn set_mtime(&mut self, v: u64) {
+        self.mtime = ::std::option::Option::Some(v);
+    }
+
+    // optional uint64 ctime = 9;
+
+    pub fn get_ctime(&self) -> u64 {
+        self.ctime.unwrap_or(0)
+    }
+    pub fn clear_ctime(&mut self) {
+        self.ctime = ::std::option::Option::None;
+    }
+
+    pub fn has_ctime(&self) -> bool {
+        self.ctime.is_some()
+    }
+
+    // Param is passed by value, moved
+    pub fn set_ctime(&mut self, v: u64) {
+        self.ctime = ::std::option::Option::Some(v);
+    }
+
+    // optional string linkname = 10;
+
+    pub fn get_linkname(&self) -> &str {
+        match self.linkname.as_ref() {
+            Some(v) => &v,
+            None => "",
+        }
+    }
+    pub fn clear_linkname(&mut self) {
+        self.linkname.clear();
+    }
+
+    pub fn has_linkname(&self) -> bool {
+        self.linkname.is_some()
+    }
+
+    // Param is passed by value, moved
+    pub fn set_linkname(&mut self, v: ::std::string::String) {
+        self.linkname = ::protobuf::SingularField::some(v);
+    }
+
+    // Mutable pointer to the field.
+    // If field is not initialized, it is initialized with default value first.
+    pub fn mut_linkname(&mut self) -> &mut ::std::string::String {
+        if self.linkname.is_none() {
+            self.linkname.set_default();
+        }
+        self.linkname.as_mut().unwrap()
+    }
+
+    // Take field
+    pub fn take_linkname(&mut self) -> ::std::string::String {
+        self.linkname
+            .take()
+            .unwrap_or_else(|| ::std::string::String::new())
+    }
+
+    // optional .Mount mount = 11;
+
+    pub fn get_mount(&self) -> &Mount {
+        self.mount
+            .as_ref()
+            .unwrap_or_else(|| <Mount as ::protobuf::Message>::default_instance())
+    }
+    pub fn clear_mount(&mut self) {
+        self.mount.clear();
+    }
+
+    pub fn has_mount(&self) -> bool {
+        self.mount.is_some()
+    }
+
+    // Param is passed by value, moved
+    pub fn set_mount(&mut self, v: Mount) {
+        self.mount = ::protobuf::SingularPtrField::some(v);
+    }
+
+    // Mutable pointer to the field.
+    // If field is not initialized, it is initialized with default value first.
+    pub fn mut_mount(&mut self) -> &mut Mount {
+        if self.mount.is_none() {
+            self.mount.set_default();
+        }
+        self.mount.as_mut().unwrap()
+    }
+
+    // Take field
+    pub fn take_mount(&mut self) -> Mount {
+        self.mount.take().unwrap_or_else(|| Mount::new())
+    }
+
+    // repeated .Stat.metadata_MapEntry metadata = 12;
+
+    pub fn get_metadata(
+        &self,
+    ) -> &::std::collections::HashMap<::std::string::String, ::std::vec::Vec<u8>> {
+        &self.metadata
+    }
+    pub fn clear_metadata(&mut self) {
+        self.metadata.clear();
+    }
+
+    // Param is passed by value, moved
+    pub fn set_metadata(
+        &mut self,
+        v: ::std::collections::HashMap<::std::string::String, ::std::vec::Vec<u8>>,
+    ) {
+        self.metadata = v;
+    }
+
+    // Mutable pointer to the field.
+    pub fn mut_metadata(
+        &mut self,
+    ) -> &mut ::std::collections::HashMap<::std::string::String, ::std::vec::Vec<u8>> {
+        &mut self.metadata
+    }
+
+    // Take field
+    pub fn take_metadata(
+        &mut self,
+    ) -> ::std::collections::HashMap<::std::string::String, ::std::vec::Vec<u8>> {
+        ::std::mem::replace(&mut self.metadata, ::std::collections::HashMap::new())
+    }
+}
+
+impl ::protobuf::Message for Stat {
+    fn is_initialized(&self) -> bool {
+        if self.mode.is_none() {
+            return false;
+        }
+        for v in &self.mount {
+            if !v.is_initialized() {
+                return false;
+            }
+        }
+        true
+    }
+
+    fn merge_from(
+        &mut self,
+        is: &mut ::protobuf::CodedInputStream<'_>,
+    ) -> ::protobuf::ProtobufResult<()> {
+        while !is.eof()? {
+            let (field_number, wire_type) = is.read_tag_unpack()?;
+            match field_number {
+                1 => {
+                    if wire_type != ::protobuf::wire_format::WireTypeVarint {
+                        return ::std::result::Result::Err(::protobuf::rt::unexpected_wire_type(
+                            wire_type,
+                        ));
+                    }
+                    let tmp = is.read_uint32()?;
+                    self.mode = ::std::option::Option::Some(tmp);
+                }
+                2 => {
+                    if wire_type != ::protobuf::wire_format::WireTypeVarint {
+                        return ::std::result::Result::Err(::protobuf::rt::unexpected_wire_type(
+                            wire_type,
+                        ));
+                    }
+                    let tmp = is.read_uint32()?;
+                    self.uid = ::std::option::Option::Some(tmp);
+                }
+                3 => {
+                    if wire_type != ::protobuf::wire_format::WireTypeVarint {
+                        return ::std::result::Result::Err(::protobuf::rt::unexpected_wire_type(
+                            wire_type,
+                        ));
+                    }
+                    let tmp = is.read_uint32()?;
+                    self.gid = ::std::option::Option::Some(tmp);
+                }
+                4 => {
+                    if wire_type != ::protobuf::wire_format::WireTypeVarint {
+                        return ::std::result::Result::Err(::protobuf::rt::unexpected_wire_type(
+                            wire_type,
+                        ));
+                    }
+                    let tmp = is.read_uint64()?;
+                    self.size = ::std::option::Option::Some(tmp);
+                }
+                5 => {
+                    if wire_type != ::protobuf::wire_format::WireTypeVarint {
+                        return ::std::result::Result::Err(::protobuf::rt::unexpected_wire_type(
+                            wire_type,
+                        ));
+                    }
+                    let tmp = is.read_uint64()?;
+                    self.blocks = ::std::option::Option::Some(tmp);
+                }
+                6 => {
+                    if wire_type != ::protobuf::wire_format::WireTypeVarint {
+                        return ::std::result::Result::Err(::protobuf::rt::unexpected_wire_type(
+                            wire_type,
+                        ));
+                    }
+                    let tmp = is.read_uint64()?;
+                    self.offset = ::std::option::Option::Some(tmp);
+                }
+                7 => {
+                    if wire_type != ::protobuf::wire_format::WireTypeVarint {
+                        return ::std::result::Result::Err(::protobuf::rt::unexpected_wire_type(
+                            wire_type,
+                        ));
+                    }
+                    let tmp = is.read_uint64()?;
+                    self.byteOffset = ::std::option::Option::Some(tmp);
+                }
+                8 => {
+                    if wire_type != ::protobuf::wire_format::WireTypeVarint {
+                        return ::std::result::Result::Err(::protobuf::rt::unexpected_wire_type(
+                            wire_type,
+                        ));
+                    }
+                    let tmp = is.read_uint64()?;
+                    self.mtime = ::std::option::Option::Some(tmp);
+                }
+                9 => {
+                    if wire_type != ::protobuf::wire_format::WireTypeVarint {
+                        return ::std::result::Result::Err(::protobuf::rt::unexpected_wire_type(
+                            wire_type,
+                        ));
+                    }
+                    let tmp = is.read_uint64()?;
+                    self.ctime = ::std::option::Option::Some(tmp);
+                }
+                10 => {
+                    ::protobuf::rt::read_singular_string_into(wire_type, is, &mut self.linkname)?;
+                }
+                11 => {
+                    ::protobuf::rt::read_singular_message_into(wire_type, is, &mut self.mount)?;
+                }
+                12 => {
+                    ::protobuf::rt::read_map_into::<
+                        ::protobuf::types::ProtobufTypeString,
+                        ::protobuf::types::ProtobufTypeBytes,
+                    >(wire_type, is, &mut self.metadata)?;
+                }
+                _ => {
+                    ::protobuf::rt::read_unknown_or_skip_group(
+                        field_number,
+                        wire_type,
+                        is,
+                        self.mut_unknown_fields(),
+                    )?;
+                }
+            };
+        }
+        ::std::result::Result::Ok(())
+    }
+
+    // Compute sizes of nested messages
+    #[allow(unused_variables)]
+    fn compute_size(&self) -> u32 {
+        let mut my_size = 0;
+        if let Some(v) = self.mode {
+            my_size += ::protobuf::rt::value_size(1, v, ::protobuf::wire_format::WireTypeVarint);
+        }
+        if let Some(v) = self.uid {
+            my_size += ::protobuf::rt::value_size(2, v, ::protobuf::wire_format::WireTypeVarint);
+        }
+        if let Some(v) = self.gid {
+            my_size += ::protobuf::rt::value_size(3, v, ::protobuf::wire_format::WireTypeVarint);
+        }
+        if let Some(v) = self.size {
+            my_size += ::protobuf::rt::value_size(4, v, ::protobuf::wire_format::WireTypeVarint);
+        }
+        if let Some(v) = self.blocks {
+            my_size += ::protobuf::rt::value_size(5, v, ::protobuf::wire_format::WireTypeVarint);
+        }
+        if let Some(v) = self.offset {
+            my_size += ::protobuf::rt::value_size(6, v, ::protobuf::wire_format::WireTypeVarint);
+        }
+        if let Some(v) = self.byteOffset {
+            my_size += ::protobuf::rt::value_size(7, v, ::protobuf::wire_format::WireTypeVarint);
+        }
+        if let Some(v) = self.mtime {
+            my_size += ::protobuf::rt::value_size(8, v, ::protobuf::wire_format::WireTypeVarint);
+        }
+        if let Some(v) = self.ctime {
+            my_size += ::protobuf::rt::value_size(9, v, ::protobuf::wire_format::WireTypeVarint);
+        }
+        if let Some(ref v) = self.linkname.as_ref() {
+            my_size += ::protobuf::rt::string_size(10, &v);
+        }
+        if let Some(ref v) = self.mount.as_ref() {
+            let len = v.compute_size();
+            my_size += 1 + ::protobuf::rt::compute_raw_varint32_size(len) + len;
+        }
+        my_size += ::protobuf::rt::compute_map_size::<
+            ::protobuf::types::ProtobufTypeString,
+            ::protobuf::types::ProtobufTypeBytes,
+        >(12, &self.metadata);
+        my_size += ::protobuf::rt::unknown_fields_size(self.get_unknown_fields());
+        self.cached_size.set(my_size);
+        my_size
+    }
+
+    fn write_to_with_cached_sizes(
+        &self,
+        os: &mut ::protobuf::CodedOutputStream<'_>,
+    ) -> ::protobuf::ProtobufResult<()> {
+        if let Some(v) = self.mode {
+            os.write_uint32(1, v)?;
+        }
+        if let Some(v) = self.uid {
+            os.write_uint32(2, v)?;
+        }
+        if let Some(v) = self.gid {
+            os.write_uint32(3, v)?;
+        }
+        if let Some(v) = self.size {
+            os.write_uint64(4, v)?;
+        }
+        if let Some(v) = self.blocks {
+            os.write_uint64(5, v)?;
+        }
+        if let Some(v) = self.offset {
+            os.write_uint64(6, v)?;
+        }
+        if let Some(v) = self.byteOffset {
+            os.write_uint64(7, v)?;
+        }
+        if let Some(v) = self.mtime {
+            os.write_uint64(8, v)?;
+        }
+        if let Some(v) = self.ctime {
+            os.write_uint64(9, v)?;
+        }
+        if let Some(ref v) = self.linkname.as_ref() {
+            os.write_string(10, &v)?;
+        }
+        if let Some(ref v) = self.mount.as_ref() {
+            os.write_tag(11, ::protobuf::wire_format::WireTypeLengthDelimited)?;
+            os.write_raw_varint32(v.get_cached_size())?;
+            v.write_to_with_cached_sizes(os)?;
+        }
+        ::protobuf::rt::write_map_with_cached_sizes::<
+            ::protobuf::types::ProtobufTypeString,
+            ::protobuf::types::ProtobufTypeBytes,
+        >(12, &self.metadata, os)?;
+        os.write_unknown_fields(self.get_unknown_fields())?;
+        ::std::result::Result::Ok(())
+    }
+
+    fn get_cached_size(&self) -> u32 {
+        self.cached_size.get()
+    }
+
+    fn get_unknown_fields(&self) -> &::protobuf::UnknownFields {
+        &self.unknown_fields
+    }
+
+    fn mut_unknown_fields(&mut self) -> &mut ::protobuf::UnknownFields {
+        &mut self.unknown_fields
+    }
+
+    fn as_any(&self) -> &dyn (::std::any::Any) {
+        self as &dyn (::std::any::Any)
+    }
+    fn as_any_mut(&mut self) -> &mut dyn (::std::any::Any) {
+        self as &mut dyn (::std::any::Any)
+    }
+    fn into_any(self: ::std::boxed::Box<Self>) -> ::std::boxed::Box<dyn (::std::any::Any)> {
+        self
+    }
+
+    fn descriptor(&self) -> &'static ::protobuf::reflect::MessageDescriptor {
+        Self::descriptor_static()
+    }
+
+    fn new() -> Stat {
+        Stat::new()
+    }
+
+    fn descriptor_static() -> &'static ::protobuf::reflect::MessageDescriptor {
+        static descriptor: ::protobuf::rt::LazyV2<::protobuf::reflect::MessageDescriptor> =
+            ::protobuf::rt::LazyV2::INIT;
+        descriptor.get(|| {
+            let mut fields = ::std::vec::Vec::new();
+            fields.push(::protobuf::reflect::accessor::make_option_accessor::<
+                _,
+                ::protobuf::types::ProtobufTypeUint32,
+            >(
+                "mode", |m: &Stat| &m.mode, |m: &mut Stat| &mut m.mode
+            ));
+            fields.push(::protobuf::reflect::accessor::make_option_accessor::<
+                _,
+                ::protobuf::types::ProtobufTypeUint32,
+            >(
+                "uid", |m: &Stat| &m.uid, |m: &mut Stat| &mut m.uid
+            ));
+            fields.push(::protobuf::reflect::accessor::make_option_accessor::<
+                _,
+                ::protobuf::types::ProtobufTypeUint32,
+            >(
+                "gid", |m: &Stat| &m.gid, |m: &mut Stat| &mut m.gid
+            ));
+            fields.push(::protobuf::reflect::accessor::make_option_accessor::<
+                _,
+                ::protobuf::types::ProtobufTypeUint64,
+            >(
+                "size", |m: &Stat| &m.size, |m: &mut Stat| &mut m.size
+            ));
+            fields.push(::protobuf::reflect::accessor::make_option_accessor::<
+                _,
+                ::protobuf::types::ProtobufTypeUint64,
+            >(
+                "blocks",
+                |m: &Stat| &m.blocks,
+                |m: &mut Stat| &mut m.blocks,
+            ));
+            fields.push(::protobuf::reflect::accessor::make_option_accessor::<
+                _,
+                ::protobuf::types::ProtobufTypeUint64,
+            >(
+                "offset",
+                |m: &Stat| &m.offset,
+                |m: &mut Stat| &mut m.offset,
+            ));
+            fields.push(::protobuf::reflect::accessor::make_option_accessor::<
+                _,
+                ::protobuf::types::ProtobufTypeUint64,
+            >(
+                "byteOffset",
+                |m: &Stat| &m.byteOffset,
+                |m: &mut Stat| &mut m.byteOffset,
+            ));
+            fields.push(::protobuf::reflect::accessor::make_option_accessor::<
+                _,
+                ::protobuf::types::ProtobufTypeUint64,
+            >(
+                "mtime", |m: &Stat| &m.mtime, |m: &mut Stat| &mut m.mtime
+            ));
+            fields.push(::protobuf::reflect::accessor::make_option_accessor::<
+                _,
+                ::protobuf::types::ProtobufTypeUint64,
+            >(
+                "ctime", |m: &Stat| &m.ctime, |m: &mut Stat| &mut m.ctime
+            ));
+            fields.push(
+                ::protobuf::reflect::accessor::make_singular_field_accessor::<
+                    _,
+                    ::protobuf::types::ProtobufTypeString,
+                >(
+                    "linkname",
+                    |m: &Stat| &m.linkname,
+                    |m: &mut Stat| &mut m.linkname,
+                ),
+            );
+            fields.push(
+                ::protobuf::reflect::accessor::make_singular_ptr_field_accessor::<
+                    _,
+                    ::protobuf::types::ProtobufTypeMessage<Mount>,
+                >("mount", |m: &Stat| &m.mount, |m: &mut Stat| &mut m.mount),
+            );
+            fields.push(::protobuf::reflect::accessor::make_map_accessor::<
+                _,
+                ::protobuf::types::ProtobufTypeString,
+                ::protobuf::types::ProtobufTypeBytes,
+            >(
+                "metadata",
+                |m: &Stat| &m.metadata,
+                |m: &mut Stat| &mut m.metadata,
+            ));
+            ::protobuf::reflect::MessageDescriptor::new_pb_name::<Stat>(
+                "Stat",
+                fields,
+                file_descriptor_proto(),
+            )
+        })
+    }
+
+    fn default_instance() -> &'static Stat {
+        static instance: ::protobuf::rt::LazyV2<Stat> = ::protobuf::rt::LazyV2::INIT;
+        instance.get(Stat::new)
+    }
+}
+
+impl ::protobuf::Clear for Stat {
+    fn clear(&mut self) {
+        self.mode = ::std::option::Option::None;
+        self.uid = ::std::option::Option::None;
+        self.gid = ::std::option::Option::None;
+        self.size = ::std::option::Option::None;
+        self.blocks = ::std::option::Option::None;
+        self.offset = ::std::option::Option::None;
+        self.byteOffset = ::std::option::Option::None;
+        self.mtime = ::std::option::Option::None;
+        self.ctime = ::std::option::Option::None;
+        self.linkname.clear();
+        self.mount.clear();
+        self.metadata.clear();
+        self.unknown_fields.clear();
+    }
+}
+
+impl ::std::fmt::Debug for Stat {
+    fn fmt(&self, f: &mut ::std::fmt::Formatter<'_>) -> ::std::fmt::Result {
+        ::protobuf::text_format::fmt(self, f)
+    }
+}
+
+impl ::protobuf::reflect::ProtobufValue for Stat {
+    fn as_ref(&self) -> ::protobuf::reflect::ReflectValueRef {
+        ::protobuf::reflect::ReflectValueRef::Message(self)
+    }
+}
+
+#[derive(PartialEq, Clone, Default)]
+pub struct Node {
+    // message fields
+    key: ::protobuf::SingularField<::std::string::String>,
+    valueBuffer: ::protobuf::SingularField<::std::vec::Vec<u8>>,
+    trieBuffer: ::protobuf::SingularField<::std::vec::Vec<u8>>,
+    seq: ::std::option::Option<u64>,
+    flags: ::std::option::Option<u64>,
+    // special fields
+    pub unknown_fields: ::protobuf::UnknownFields,
+    pub cached_size: ::protobuf::CachedSize,
+}
+
+impl<'a> ::std::default::Default for &'a Node {
+    fn default() -> &'a Node {
+        <Node as ::protobuf::Message>::default_instance()
+    }
+}
+
+impl Node {
+    pub fn new() -> Node {
+        ::std::default::Default::default()
+    }
+
+    // required string key = 1;
+
+    pub fn get_key(&self) -> &str {
+        match self.key.as_ref() {
+            Some(v) => &v,
+            None => "",
+        }
+    }
+    pub fn clear_key(&mut self) {
+        self.key.clear();
+    }
+
+    pub fn has_key(&self) -> bool {
+        self.key.is_some()
+    }
+
+    // Param is passed by value, moved
+    pub fn set_key(&mut self, v: ::std::string::String) {
+        self.key = ::protobuf::SingularField::some(v);
+    }
+
+    // Mutable pointer to the field.
+    // If field is not initialized, it is initialized with default value first.
+    pub fn mut_key(&mut self) -> &mut ::std::string::String {
+        if self.key.is_none() {
+            self.key.set_default();
+        }
+        self.key.as_mut().unwrap()
+    }
+
+    // Take field
+    pub fn take_key(&mut self) -> ::std::string::String {
+        self.key
+            .take()
+            .unwrap_or_else(|| ::std::string::String::new())
+    }
+
+    // optional bytes valueBuffer = 2;
 
-    pub fn get_offset(&self) -> u64 {
-        self.offset.unwrap_or(0)
+    pub fn get_valueBuffer(&self) -> &[u8] {
+        match self.valueBuffer.as_ref() {
+            Some(v) => &v,
+            None => &[],
+        }
     }
-    pub fn clear_offset(&mut self) {
-        self.offset = ::std::option::Option::None;
+    pub fn clear_valueBuffer(&mut self) {
+        self.valueBuffer.clear();
     }
 
-    pub fn has_offset(&self) -> bool {
-        self.offset.is_some()
+    pub fn has_valueBuffer(&self) -> bool {
+        self.valueBuffer.is_some()
     }
 
     // Param is passed by value, moved
-    pub fn set_offset(&mut self, v: u64) {
-        self.offset = ::std::option::Option::Some(v);
+    pub fn set_valueBuffer(&mut self, v: ::std::vec::Vec<u8>) {
+        self.valueBuffer = ::protobuf::SingularField::some(v);
     }
 
-    // optional uint64 byteOffset = 7;
+    // Mutable pointer to the field.
+    // If field is not initialized, it is initialized with default value first.
+    pub fn mut_valueBuffer(&mut self) -> &mut ::std::vec::Vec<u8> {
+        if self.valueBuffer.is_none() {
+            self.valueBuffer.set_default();
+        }
+        self.valueBuffer.as_mut().unwrap()
+    }
 
-    pub fn get_byteOffset(&self) -> u64 {
-        self.byteOffset.unwrap_or(0)
+    // Take field
+    pub fn take_valueBuffer(&mut self) -> ::std::vec::Vec<u8> {
+        self.valueBuffer
+            .take()
+            .unwrap_or_else(|| ::std::vec::Vec::new())
     }
-    pub fn clear_byteOffset(&mut self) {
-        self.byteOffset = ::std::option::Option::None;
+
+    // optional bytes trieBuffer = 3;
+
+    pub fn get_trieBuffer(&self) -> &[u8] {
+        match self.trieBuffer.as_ref() {
+            Some(v) => &v,
+            None => &[],
+        }
+    }
+    pub fn clear_trieBuffer(&mut self) {
+        self.trieBuffer.clear();
     }
 
-    pub fn has_byteOffset(&self) -> bool {
-        self.byteOffset.is_some()
+    pub fn has_trieBuffer(&self) -> bool {
+        self.trieBuffer.is_some()
     }
 
     // Param is passed by value, moved
-    pub fn set_byteOffset(&mut self, v: u64) {
-        self.byteOffset = ::std::option::Option::Some(v);
+    pub fn set_trieBuffer(&mut self, v: ::std::vec::Vec<u8>) {
+        self.trieBuffer = ::protobuf::SingularField::some(v);
     }
 
-    // optional uint64 mtime = 8;
+    // Mutable pointer to the field.
+    // If field is not initialized, it is initialized with default value first.
+    pub fn mut_trieBuffer(&mut self) -> &mut ::std::vec::Vec<u8> {
+        if self.trieBuffer.is_none() {
+            self.trieBuffer.set_default();
+        }
+        self.trieBuffer.as_mut().unwrap()
+    }
 
-    pub fn get_mtime(&self) -> u64 {
-        self.mtime.unwrap_or(0)
+    // Take field
+    pub fn take_trieBuffer(&mut self) -> ::std::vec::Vec<u8> {
+        self.trieBuffer
+            .take()
+            .unwrap_or_else(|| ::std::vec::Vec::new())
     }
-    pub fn clear_mtime(&mut self) {
-        self.mtime = ::std::option::Option::None;
+
+    // optional uint64 seq = 4;
+
+    pub fn get_seq(&self) -> u64 {
+        self.seq.unwrap_or(0)
+    }
+    pub fn clear_seq(&mut self) {
+        self.seq = ::std::option::Option::None;
     }
 
-    pub fn has_mtime(&self) -> bool {
-        self.mtime.is_some()
+    pub fn has_seq(&self) -> bool {
+        self.seq.is_some()
     }
 
     // Param is passed by value, moved
-    pub fn set_mtime(&mut self, v: u64) {
-        self.mtime = ::std::option::Option::Some(v);
+    pub fn set_seq(&mut self, v: u64) {
+        self.seq = ::std::option::Option::Some(v);
     }
 
-    // optional uint64 ctime = 9;
+    // optional uint64 flags = 5;
 
-    pub fn get_ctime(&self) -> u64 {
-        self.ctime.unwrap_or(0)
+    pub fn get_flags(&self) -> u64 {
+        self.flags.unwrap_or(0)
     }
-    pub fn clear_ctime(&mut self) {
-        self.ctime = ::std::option::Option::None;
+    pub fn clear_flags(&mut self) {
+        self.flags = ::std::option::Option::None;
     }
 
-    pub fn has_ctime(&self) -> bool {
-        self.ctime.is_some()
+    pub fn has_flags(&self) -> bool {
+        self.flags.is_some()
     }
 
     // Param is passed by value, moved
-    pub fn set_ctime(&mut self, v: u64) {
-        self.ctime = ::std::option::Option::Some(v);
+    pub fn set_flags(&mut self, v: u64) {
+        self.flags = ::std::option::Option::Some(v);
     }
 }
 
-impl ::protobuf::Message for Stat {
+impl ::protobuf::Message for Node {
     fn is_initialized(&self) -> bool {
-        if self.mode.is_none() {
+        if self.key.is_none() {
             return false;
         }
         true
@@ -478,31 +1769,13 @@ impl ::protobuf::Message for Stat {
             let (field_number, wire_type) = is.read_tag_unpack()?;
             match field_number {
                 1 => {
-                    if wire_type != ::protobuf::wire_format::WireTypeVarint {
-                        return ::std::result::Result::Err(::protobuf::rt::unexpected_wire_type(
-                            wire_type,
-                        ));
-                    }
-                    let tmp = is.read_uint32()?;
-                    self.mode = ::std::option::Option::Some(tmp);
+                    ::protobuf::rt::read_singular_string_into(wire_type, is, &mut self.key)?;
                 }
                 2 => {
-                    if wire_type != ::protobuf::wire_format::WireTypeVarint {
-                        return ::std::result::Result::Err(::protobuf::rt::unexpected_wire_type(
-                            wire_type,
-                        ));
-                    }
-                    let tmp = is.read_uint32()?;
-                    self.uid = ::std::option::Option::Some(tmp);
+                    ::protobuf::rt::read_singular_bytes_into(wire_type, is, &mut self.valueBuffer)?;
                 }
                 3 => {
-                    if wire_type != ::protobuf::wire_format::WireTypeVarint {
-                        return ::std::result::Result::Err(::protobuf::rt::unexpected_wire_type(
-                            wire_type,
-                        ));
-                    }
-                    let tmp = is.read_uint32()?;
-                    self.gid = ::std::option::Option::Some(tmp);
+                    ::protobuf::rt::read_singular_bytes_into(wire_type, is, &mut self.trieBuffer)?;
                 }
                 4 => {
                     if wire_type != ::protobuf::wire_format::WireTypeVarint {
@@ -511,7 +1784,7 @@ impl ::protobuf::Message for Stat {
                         ));
                     }
                     let tmp = is.read_uint64()?;
-                    self.size = ::std::option::Option::Some(tmp);
+                    self.seq = ::std::option::Option::Some(tmp);
                 }
                 5 => {
                     if wire_type != ::protobuf::wire_format::WireTypeVarint {
@@ -520,43 +1793,7 @@ impl ::protobuf::Message for Stat {
                         ));
                     }
                     let tmp = is.read_uint64()?;
-                    self.blocks = ::std::option::Option::Some(tmp);
-                }
-                6 => {
-                    if wire_type != ::protobuf::wire_format::WireTypeVarint {
-                        return ::std::result::Result::Err(::protobuf::rt::unexpected_wire_type(
-                            wire_type,
-                        ));
-                    }
-                    let tmp = is.read_uint64()?;
-                    self.offset = ::std::option::Option::Some(tmp);
-                }
-                7 => {
-                    if wire_type != ::protobuf::wire_format::WireTypeVarint {
-                        return ::std::result::Result::Err(::protobuf::rt::unexpected_wire_type(
-                            wire_type,
-                        ));
-                    }
-                    let tmp = is.read_uint64()?;
-                    self.byteOffset = ::std::option::Option::Some(tmp);
-                }
-                8 => {
-                    if wire_type != ::protobuf::wire_format::WireTypeVarint {
-                        return ::std::result::Result::Err(::protobuf::rt::unexpected_wire_type(
-                            wire_type,
-                        ));
-                    }
-                    let tmp = is.read_uint64()?;
-                    self.mtime = ::std::option::Option::Some(tmp);
-                }
-                9 => {
-                    if wire_type != ::protobuf::wire_format::WireTypeVarint {
-                        return ::std::result::Result::Err(::protobuf::rt::unexpected_wire_type(
-                            wire_type,
-                        ));
-                    }
-                    let tmp = is.read_uint64()?;
-                    self.ctime = ::std::option::Option::Some(tmp);
+                    self.flags = ::std::option::Option::Some(tmp);
                 }
                 _ => {
                     ::protobuf::rt::read_unknown_or_skip_group(
@@ -575,33 +1812,21 @@ impl ::protobuf::Message for Stat {
     #[allow(unused_variables)]
     fn compute_size(&self) -> u32 {
         let mut my_size = 0;
-        if let Some(v) = self.mode {
-            my_size += ::protobuf::rt::value_size(1, v, ::protobuf::wire_format::WireTypeVarint);
+        if let Some(ref v) = self.key.as_ref() {
+            my_size += ::protobuf::rt::string_size(1, &v);
         }
-        if let Some(v) = self.uid {
-            my_size += ::protobuf::rt::value_size(2, v, ::protobuf::wire_format::WireTypeVarint);
+        if let Some(ref v) = self.valueBuffer.as_ref() {
+            my_size += ::protobuf::rt::bytes_size(2, &v);
         }
-        if let Some(v) = self.gid {
-            my_size += ::protobuf::rt::value_size(3, v, ::protobuf::wire_format::WireTypeVarint);
+        if let Some(ref v) = self.trieBuffer.as_ref() {
+            my_size += ::protobuf::rt::bytes_size(3, &v);
         }
-        if let Some(v) = self.size {
+        if let Some(v) = self.seq {
             my_size += ::protobuf::rt::value_size(4, v, ::protobuf::wire_format::WireTypeVarint);
         }
-        if let Some(v) = self.blocks {
+        if let Some(v) = self.flags {
             my_size += ::protobuf::rt::value_size(5, v, ::protobuf::wire_format::WireTypeVarint);
         }
-        if let Some(v) = self.offset {
-            my_size += ::protobuf::rt::value_size(6, v, ::protobuf::wire_format::WireTypeVarint);
-        }
-        if let Some(v) = self.byteOffset {
-            my_size += ::protobuf::rt::value_size(7, v, ::protobuf::wire_format::WireTypeVarint);
-        }
-        if let Some(v) = self.mtime {
-            my_size += ::protobuf::rt::value_size(8, v, ::protobuf::wire_format::WireTypeVarint);
-        }
-        if let Some(v) = self.ctime {
-            my_size += ::protobuf::rt::value_size(9, v, ::protobuf::wire_format::WireTypeVarint);
-        }
         my_size += ::protobuf::rt::unknown_fields_size(self.get_unknown_fields());
         self.cached_size.set(my_size);
         my_size
@@ -611,33 +1836,21 @@ impl ::protobuf::Message for Stat {
         &self,
         os: &mut ::protobuf::CodedOutputStream<'_>,
     ) -> ::protobuf::ProtobufResult<()> {
-        if let Some(v) = self.mode {
-            os.write_uint32(1, v)?;
+        if let Some(ref v) = self.key.as_ref() {
+            os.write_string(1, &v)?;
         }
-        if let Some(v) = self.uid {
-            os.write_uint32(2, v)?;
+        if let Some(ref v) = self.valueBuffer.as_ref() {
+            os.write_bytes(2, &v)?;
         }
-        if let Some(v) = self.gid {
-            os.write_uint32(3, v)?;
+        if let Some(ref v) = self.trieBuffer.as_ref() {
+            os.write_bytes(3, &v)?;
         }
-        if let Some(v) = self.size {
+        if let Some(v) = self.seq {
             os.write_uint64(4, v)?;
         }
-        if let Some(v) = self.blocks {
+        if let Some(v) = self.flags {
             os.write_uint64(5, v)?;
         }
-        if let Some(v) = self.offset {
-            os.write_uint64(6, v)?;
-        }
-        if let Some(v) = self.byteOffset {
-            os.write_uint64(7, v)?;
-        }
-        if let Some(v) = self.mtime {
-            os.write_uint64(8, v)?;
-        }
-        if let Some(v) = self.ctime {
-            os.write_uint64(9, v)?;
-        }
         os.write_unknown_fields(self.get_unknown_fields())?;
         ::std::result::Result::Ok(())
     }
@@ -668,8 +1881,8 @@ impl ::protobuf::Message for Stat {
         Self::descriptor_static()
     }
 
-    fn new() -> Stat {
-        Stat::new()
+    fn new() -> Node {
+        Node::new()
     }
 
     fn descriptor_static() -> &'static ::protobuf::reflect::MessageDescriptor {
@@ -677,116 +1890,102 @@ impl ::protobuf::Message for Stat {
             ::protobuf::rt::LazyV2::INIT;
         descriptor.get(|| {
             let mut fields = ::std::vec::Vec::new();
-            fields.push(::protobuf::reflect::accessor::make_option_accessor::<
-                _,
-                ::protobuf::types::ProtobufTypeUint32,
-            >(
-                "mode", |m: &Stat| &m.mode, |m: &mut Stat| &mut m.mode
-            ));
-            fields.push(::protobuf::reflect::accessor::make_option_accessor::<
-                _,
-                ::protobuf::types::ProtobufTypeUint32,
-            >(
-                "uid", |m: &Stat| &m.uid, |m: &mut Stat| &mut m.uid
-            ));
-            fields.push(::protobuf::reflect::accessor::make_option_accessor::<
-                _,
-                ::protobuf::types::ProtobufTypeUint32,
-            >(
-                "gid", |m: &Stat| &m.gid, |m: &mut Stat| &mut m.gid
-            ));
-            fields.push(::protobuf::reflect::accessor::make_option_accessor::<
-                _,
-                ::protobuf::types::ProtobufTypeUint64,
-            >(
-                "size", |m: &Stat| &m.size, |m: &mut Stat| &mut m.size
-            ));
-            fields.push(::protobuf::reflect::accessor::make_option_accessor::<
-                _,
-                ::protobuf::types::ProtobufTypeUint64,
-            >(
-                "blocks",
-                |m: &Stat| &m.blocks,
-                |m: &mut Stat| &mut m.blocks,
-            ));
-            fields.push(::protobuf::reflect::accessor::make_option_accessor::<
-                _,
-                ::protobuf::types::ProtobufTypeUint64,
-            >(
-                "offset",
-                |m: &Stat| &m.offset,
-                |m: &mut Stat| &mut m.offset,
-            ));
-            fields.push(::protobuf::reflect::accessor::make_option_accessor::<
-                _,
-                ::protobuf::types::ProtobufTypeUint64,
-            >(
-                "byteOffset",
-                |m: &Stat| &m.byteOffset,
-                |m: &mut Stat| &mut m.byteOffset,
-            ));
+            fields.push(
+                ::protobuf::reflect::accessor::make_singular_field_accessor::<
+                    _,
+                    ::protobuf::types::ProtobufTypeString,
+                >("key", |m: &Node| &m.key, |m: &mut Node| &mut m.key),
+            );
+            fields.push(
+                ::protobuf::reflect::accessor::make_singular_field_accessor::<
+                    _,
+                    ::protobuf::types::ProtobufTypeBytes,
+                >(
+                    "valueBuffer",
+                    |m: &Node| &m.valueBuffer,
+                    |m: &mut Node| &mut m.valueBuffer,
+                ),
+            );
+            fields.push(
+                ::protobuf::reflect::accessor::make_singular_field_accessor::<
+                    _,
+                    ::protobuf::types::ProtobufTypeBytes,
+                >(
+                    "trieBuffer",
+                    |m: &Node| &m.trieBuffer,
+                    |m: &mut Node| &mut m.trieBuffer,
+                ),
+            );
             fields.push(::protobuf::reflect::accessor::make_option_accessor::<
                 _,
                 ::protobuf::types::ProtobufTypeUint64,
             >(
-                "mtime", |m: &Stat| &m.mtime, |m: &mut Stat| &mut m.mtime
+                "seq", |m: &Node| &m.seq, |m: &mut Node| &mut m.seq
             ));
             fields.push(::protobuf::reflect::accessor::make_option_accessor::<
                 _,
                 ::protobuf::types::ProtobufTypeUint64,
             >(
-                "ctime", |m: &Stat| &m.ctime, |m: &mut Stat| &mut m.ctime
+                "flags", |m: &Node| &m.flags, |m: &mut Node| &mut m.flags
             ));
-            ::protobuf::reflect::MessageDescriptor::new_pb_name::<Stat>(
-                "Stat",
+            ::protobuf::reflect::MessageDescriptor::new_pb_name::<Node>(
+                "Node",
                 fields,
                 file_descriptor_proto(),
             )
         })
     }
 
-    fn default_instance() -> &'static Stat {
-        static instance: ::protobuf::rt::LazyV2<Stat> = ::protobuf::rt::LazyV2::INIT;
-        instance.get(Stat::new)
+    fn default_instance() -> &'static Node {
+        static instance: ::protobuf::rt::LazyV2<Node> = ::protobuf::rt::LazyV2::INIT;
+        instance.get(Node::new)
     }
 }
 
-impl ::protobuf::Clear for Stat {
+impl ::protobuf::Clear for Node {
     fn clear(&mut self) {
-        self.mode = ::std::option::Option::None;
-        self.uid = ::std::option::Option::None;
-        self.gid = ::std::option::Option::None;
-        self.size = ::std::option::Option::None;
-        self.blocks = ::std::option::Option::None;
-        self.offset = ::std::option::Option::None;
-        self.byteOffset = ::std::option::Option::None;
-        self.mtime = ::std::option::Option::None;
-        self.ctime = ::std::option::Option::None;
+        self.key.clear();
+        self.valueBuffer.clear();
+        self.trieBuffer.clear();
+        self.seq = ::std::option::Option::None;
+        self.flags = ::std::option::Option::None;
         self.unknown_fields.clear();
     }
 }
 
-impl ::std::fmt::Debug for Stat {
+impl ::std::fmt::Debug for Node {
     fn fmt(&self, f: &mut ::std::fmt::Formatter<'_>) -> ::std::fmt::Result {
         ::protobuf::text_format::fmt(self, f)
     }
 }
 
-impl ::protobuf::reflect::ProtobufValue for Stat {
+impl ::protobuf::reflect::ProtobufValue for Node {
     fn as_ref(&self) -> ::protobuf::reflect::ReflectValueRef {
         ::protobuf::reflect::ReflectValueRef::Message(self)
     }
 }
 
 static file_descriptor_proto_data: &'static [u8] = b"\
-    \n\x0cschema.proto\x12\0\",\n\x05Index\x12\x0e\n\x04type\x18\x01\x20\x02\
-    (\tB\0\x12\x11\n\x07content\x18\x02\x20\x01(\x0cB\0:\0\"\xa2\x01\n\x04St\
-    at\x12\x0e\n\x04mode\x18\x01\x20\x02(\rB\0\x12\r\n\x03uid\x18\x02\x20\
-    \x01(\rB\0\x12\r\n\x03gid\x18\x03\x20\x01(\rB\0\x12\x0e\n\x04size\x18\
-    \x04\x20\x01(\x04B\0\x12\x10\n\x06blocks\x18\x05\x20\x01(\x04B\0\x12\x10\
-    \n\x06offset\x18\x06\x20\x01(\x04B\0\x12\x14\n\nbyteOffset\x18\x07\x20\
-    \x01(\x04B\0\x12\x0f\n\x05mtime\x18\x08\x20\x01(\x04B\0\x12\x0f\n\x05cti\
-    me\x18\t\x20\x01(\x04B\0:\0B\0b\x06proto2\
+    \n\x0cschema.proto\x12\0\"A\n\x06Header\x12\x0e\n\x04type\x18\x01\x20\
+    \x02(\tB\0\x12\x12\n\x08metadata\x18\x02\x20\x01(\x0cB\0\x12\x11\n\x07su\
+    btype\x18\x03\x20\x01(\tB\0:\0\",\n\x05Index\x12\x0e\n\x04type\x18\x01\
+    \x20\x02(\tB\0\x12\x11\n\x07content\x18\x02\x20\x01(\x0cB\0:\0\"P\n\x05M\
+    ount\x12\r\n\x03key\x18\x01\x20\x02(\x0cB\0\x12\x11\n\x07version\x18\x02\
+    \x20\x01(\x04B\0\x12\x0e\n\x04hash\x18\x03\x20\x01(\x0cB\0\x12\x13\n\thy\
+    percore\x18\x04\x20\x01(\x08B\0:\0\"\xad\x02\n\x04Stat\x12\x0e\n\x04mode\
+    \x18\x01\x20\x02(\rB\0\x12\r\n\x03uid\x18\x02\x20\x01(\rB\0\x12\r\n\x03g\
+    id\x18\x03\x20\x01(\rB\0\x12\x0e\n\x04size\x18\x04\x20\x01(\x04B\0\x12\
+    \x10\n\x06blocks\x18\x05\x20\x01(\x04B\0\x12\x10\n\x06offset\x18\x06\x20\
+    \x01(\x04B\0\x12\x14\n\nbyteOffset\x18\x07\x20\x01(\x04B\0\x12\x0f\n\x05\
+    mtime\x18\x08\x20\x01(\x04B\0\x12\x0f\n\x05ctime\x18\t\x20\x01(\x04B\0\
+    \x12\x12\n\x08linkname\x18\n\x20\x01(\tB\0\x12\x17\n\x05mount\x18\x0b\
+    \x20\x01(\x0b2\x06.MountB\0\x12+\n\x08metadata\x18\x0c\x20\x03(\x0b2\x17\
+    .Stat.metadata_MapEntryB\0\x1a/\n\x11metadata_MapEntry\x12\t\n\x03key\
+    \x18\x01(\t\x12\x0b\n\x05value\x18\x02(\x0c:\x028\x01:\0\"d\n\x04Node\
+    \x12\r\n\x03key\x18\x01\x20\x02(\tB\0\x12\x15\n\x0bvalueBuffer\x18\x02\
+    \x20\x01(\x0cB\0\x12\x14\n\ntrieBuffer\x18\x03\x20\x01(\x0cB\0\x12\r\n\
+    \x03seq\x18\x04\x20\x01(\x04B\0\x12\x0f\n\x05flags\x18\x05\x20\x01(\x04B\
+    \0:\0B\0b\x06proto2\
 ";
 
 static file_descriptor_proto_lazy: ::protobuf::rt::LazyV2<
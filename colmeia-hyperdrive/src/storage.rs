@@ -0,0 +1,67 @@
+use anyhow::Context;
+use std::path::{Path, PathBuf};
+
+/// Opens the storage of each feed of a drive, as the content feed key is only known
+/// after the metadata is available.
+#[async_trait::async_trait]
+pub trait FeedStorage<Storage>: Send + Sync
+where
+    Storage: random_access_storage::RandomAccess<Error = Box<dyn std::error::Error + Send + Sync>>
+        + std::fmt::Debug
+        + Send
+        + Sync,
+{
+    async fn open(
+        &self,
+        public_key: &hypercore::PublicKey,
+    ) -> anyhow::Result<hypercore::Storage<Storage>>;
+}
+
+pub struct MemoryStorage;
+
+#[async_trait::async_trait]
+impl FeedStorage<random_access_memory::RandomAccessMemory> for MemoryStorage {
+    async fn open(
+        &self,
+        _public_key: &hypercore::PublicKey,
+    ) -> anyhow::Result<hypercore::Storage<random_access_memory::RandomAccessMemory>> {
+        hypercore::Storage::new_memory()
+            .await
+            .context("could not page feed memory")
+    }
+}
+
+/// Keeps each feed in a folder named after its discovery key, like corestore does:
+/// `<root>/ab/cd/abcd...`
+pub struct DiskStorage {
+    root: PathBuf,
+}
+
+impl DiskStorage {
+    pub fn new(root: impl AsRef<Path>) -> Self {
+        Self {
+            root: root.as_ref().to_path_buf(),
+        }
+    }
+
+    pub fn feed_path(&self, public_key: &hypercore::PublicKey) -> PathBuf {
+        let discovery_key = hex::encode(hypercore_protocol::discovery_key(public_key.as_bytes()));
+        self.root
+            .join(&discovery_key[0..2])
+            .join(&discovery_key[2..4])
+            .join(&discovery_key)
+    }
+}
+
+#[async_trait::async_trait]
+impl FeedStorage<random_access_disk::RandomAccessDisk> for DiskStorage {
+    async fn open(
+        &self,
+        public_key: &hypercore::PublicKey,
+    ) -> anyhow::Result<hypercore::Storage<random_access_disk::RandomAccessDisk>> {
+        let path = self.feed_path(public_key);
+        hypercore::Storage::new_disk(&path, false)
+            .await
+            .with_context(|| format!("could not open feed storage on {}", path.display()))
+    }
+}
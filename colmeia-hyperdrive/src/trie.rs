@@ -0,0 +1,557 @@
+use anyhow::Context;
+use async_std::sync::RwLock;
+use colmeia_hypercore::FeedHub;
+use std::hash::Hasher;
+use std::sync::Arc;
+
+// Each key component hashes to 8 bytes, read as 2 bits at a time
+const HASH_LENGTH: usize = 8;
+// Value on the path after the last hash bit
+const TERMINATOR: u8 = 4;
+
+/// Trims the slashes around a path, like hypertrie keys are stored
+pub(crate) fn normalize(key: &str) -> &str {
+    key.trim_matches('/')
+}
+
+pub(crate) fn split(key: &str) -> Vec<&str> {
+    normalize(key)
+        .split('/')
+        .filter(|component| !component.is_empty())
+        .collect()
+}
+
+fn hash(components: &[&str]) -> Vec<u8> {
+    let mut hash = Vec::with_capacity(components.len() * HASH_LENGTH);
+    for component in components {
+        // Same as sodium crypto_shorthash with an empty key
+        let mut hasher = siphasher::sip::SipHasher24::new_with_keys(0, 0);
+        hasher.write(component.as_bytes());
+        hash.extend_from_slice(&hasher.finish().to_le_bytes());
+    }
+    hash
+}
+
+/// The value at position `i` of the hash path, where position 0 tells hidden keys apart
+fn path(hash: &[u8], hidden: bool, i: usize) -> u8 {
+    if i == 0 {
+        return hidden as u8;
+    }
+    let i = i - 1;
+    match hash.get(i >> 2) {
+        Some(byte) => (byte >> (2 * (i & 3))) & 3,
+        None => TERMINATOR,
+    }
+}
+
+fn read_varint(buf: &[u8], ptr: &mut usize) -> anyhow::Result<u64> {
+    let mut value = 0u64;
+    let mut shift = 0;
+    loop {
+        let byte = *buf
+            .get(*ptr)
+            .context("trie buffer ended in the middle of a varint")?;
+        *ptr += 1;
+        value |= u64::from(byte & 0x7f) << shift;
+        if byte & 0x80 == 0 {
+            return Ok(value);
+        }
+        shift += 7;
+        anyhow::ensure!(shift < 64, "varint on trie buffer is too long");
+    }
+}
+
+fn write_varint(buf: &mut Vec<u8>, mut value: u64) {
+    while value >= 0x80 {
+        buf.push((value as u8 & 0x7f) | 0x80);
+        value >>= 7;
+    }
+    buf.push(value as u8);
+}
+
+/// Decodes the trie buffer: a list of (position, bitfield of values, seq for each value).
+/// Positions go up to `len`, the length of the hash path of the node.
+fn decode_trie(buf: &[u8], len: usize) -> anyhow::Result<Vec<[Option<u64>; 5]>> {
+    let mut trie = Vec::new();
+    let mut ptr = 0;
+    while ptr < buf.len() {
+        let index = read_varint(buf, &mut ptr)?;
+        anyhow::ensure!(
+            index < len as u64,
+            "trie buffer points past the end of the node path"
+        );
+        let index = index as usize;
+        let mut bitfield = read_varint(buf, &mut ptr)?;
+        if trie.len() <= index {
+            trie.resize(index + 1, [None; 5]);
+        }
+        let mut value = 0;
+        while bitfield != 0 {
+            if bitfield & 1 == 1 {
+                let seq = read_varint(buf, &mut ptr)?;
+                if let Some(slot) = trie[index].get_mut(value) {
+                    *slot = Some(seq);
+                }
+            }
+            bitfield >>= 1;
+            value += 1;
+        }
+    }
+    Ok(trie)
+}
+
+fn encode_trie(trie: &[[Option<u64>; 5]]) -> Vec<u8> {
+    let mut buf = Vec::new();
+    for (index, bucket) in trie.iter().enumerate() {
+        let bitfield = bucket
+            .iter()
+            .enumerate()
+            .filter(|(_, seq)| seq.is_some())
+            .fold(0, |bitfield, (value, _)| bitfield | 1 << value);
+        if bitfield == 0 {
+            continue;
+        }
+        write_varint(&mut buf, index as u64);
+        write_varint(&mut buf, bitfield);
+        for seq in bucket.iter().flatten() {
+            write_varint(&mut buf, *seq);
+        }
+    }
+    buf
+}
+
+/// A hypertrie node stored on the metadata feed
+#[derive(Debug, Clone)]
+pub(crate) struct Node {
+    pub seq: u64,
+    pub key: String,
+    pub value: Option<Vec<u8>>,
+    pub flags: u64,
+    pub hidden: bool,
+    hash: Vec<u8>,
+    trie: Vec<[Option<u64>; 5]>,
+}
+
+impl Node {
+    fn decode(seq: u64, bytes: &[u8]) -> anyhow::Result<Self> {
+        let mut node = protobuf::parse_from_bytes::<crate::schema::Node>(bytes)
+            .context("metadata entry is not a hypertrie node")?;
+        let key = normalize(node.get_key()).to_string();
+        let hash = hash(&split(&key));
+        let trie = decode_trie(node.get_trieBuffer(), hash.len() * 4 + 2)?;
+        Ok(Self {
+            seq,
+            flags: node.get_flags(),
+            hidden: node.get_flags() & 1 == 1,
+            value: if node.has_valueBuffer() {
+                Some(node.take_valueBuffer())
+            } else {
+                None
+            },
+            key,
+            hash,
+            trie,
+        })
+    }
+
+    fn len(&self) -> usize {
+        self.hash.len() * 4 + 2
+    }
+
+    fn path(&self, i: usize) -> u8 {
+        path(&self.hash, self.hidden, i)
+    }
+
+    fn pointer(&self, i: usize, value: u8) -> Option<u64> {
+        self.trie.get(i)?.get(value as usize).copied().flatten()
+    }
+}
+
+/// Read access to the hypertrie kept on the metadata feed.
+/// Missing blocks are downloaded through the hub.
+/// With a `version`, only the entries up to that metadata length are seen.
+pub(crate) struct Trie<'a, Storage>
+where
+    Storage: random_access_storage::RandomAccess<Error = Box<dyn std::error::Error + Send + Sync>>
+        + std::fmt::Debug
+        + Send
+        + Sync,
+{
+    pub feed: &'a Arc<RwLock<hypercore::Feed<Storage>>>,
+    pub hub: &'a FeedHub,
+    pub version: Option<u64>,
+}
+
+impl<'a, Storage> Trie<'a, Storage>
+where
+    Storage: random_access_storage::RandomAccess<Error = Box<dyn std::error::Error + Send + Sync>>
+        + std::fmt::Debug
+        + Send
+        + Sync,
+{
+    pub async fn node(&self, seq: u64) -> anyhow::Result<Node> {
+        self.hub.download_block(self.feed, seq).await?;
+        let block = self
+            .feed
+            .write()
+            .await
+            .get(seq)
+            .await?
+            .with_context(|| format!("metadata block {} is missing", seq))?;
+        Node::decode(seq, &block)
+    }
+
+    pub async fn len(&self) -> u64 {
+        let len = self.feed.read().await.len();
+        match self.version {
+            Some(version) => version.min(len),
+            None => len,
+        }
+    }
+
+    /// The latest node, block 0 is the drive header
+    async fn head(&self) -> anyhow::Result<Option<Node>> {
+        let len = self.len().await;
+        if len <= 1 {
+            return Ok(None);
+        }
+        Ok(Some(self.node(len - 1).await?))
+    }
+
+    /// Follows the trie until a node matching the hash path up to `end` is found
+    async fn seek(
+        &self,
+        mut node: Node,
+        target: &[u8],
+        end: usize,
+    ) -> anyhow::Result<Option<(Node, usize)>> {
+        let mut i = 0;
+        while i < end {
+            let value = path(target, false, i);
+            if node.path(i) != value {
+                node = match node.pointer(i, value) {
+                    Some(seq) => self.node(seq).await?,
+                    None => return Ok(None),
+                };
+            }
+            i += 1;
+        }
+        Ok(Some((node, i)))
+    }
+
+    pub async fn get(&self, key: &str) -> anyhow::Result<Option<Node>> {
+        let head = match self.head().await? {
+            Some(head) => head,
+            None => return Ok(None),
+        };
+        let key = normalize(key);
+        let target = hash(&split(key));
+        let end = target.len() * 4 + 2;
+        match self.seek(head, &target, end).await? {
+            // Deletions rewrite other nodes, so a node without value is an empty trie
+            Some((node, _)) if node.key == key && node.value.is_some() => Ok(Some(node)),
+            _ => Ok(None),
+        }
+    }
+
+    /// Every key under the prefix, including the prefix itself
+    pub async fn list(&self, prefix: &str) -> anyhow::Result<Vec<Node>> {
+        self.walk(prefix, true, None).await
+    }
+
+    /// A key under the prefix for each name directly inside it
+    pub async fn children(&self, prefix: &str) -> anyhow::Result<Vec<Node>> {
+        self.walk(prefix, false, None).await
+    }
+
+    /// Any key under the prefix, the walk stops at the first one found
+    pub async fn first(&self, prefix: &str) -> anyhow::Result<Option<Node>> {
+        Ok(self.walk(prefix, false, Some(1)).await?.pop())
+    }
+
+    /// Keys under the prefix, up to `limit` of them.
+    /// Without `recursive`, the keys inside a name are not walked once one of them is found.
+    async fn walk(
+        &self,
+        prefix: &str,
+        recursive: bool,
+        limit: Option<usize>,
+    ) -> anyhow::Result<Vec<Node>> {
+        let head = match self.head().await? {
+            Some(head) => head,
+            None => return Ok(vec![]),
+        };
+        let components = split(prefix);
+        let target = hash(&components);
+        // Position 0 and the bits of the prefix components, leaving the terminator out
+        let end = target.len() * 4 + 1;
+        let (node, start) = match self.seek(head, &target, end).await? {
+            Some(found) => found,
+            None => return Ok(vec![]),
+        };
+
+        let mut nodes = Vec::new();
+        let mut stack = vec![(node, start)];
+        while let Some((node, start)) = stack.pop() {
+            let inside = split(&node.key).starts_with(&components);
+            let found = inside && node.value.is_some() && !node.hidden;
+            if found && limit == Some(nodes.len() + 1) {
+                nodes.push(node);
+                break;
+            }
+
+            // Past the bits of the next name, the pointers go to keys inside that same name
+            let last = if found && !recursive {
+                node.len().min(end + HASH_LENGTH * 4)
+            } else {
+                node.len()
+            };
+            for i in start..last {
+                for value in 0..=TERMINATOR {
+                    if value == node.path(i) {
+                        continue;
+                    }
+                    if let Some(seq) = node.pointer(i, value) {
+                        stack.push((self.node(seq).await?, i + 1));
+                    }
+                }
+            }
+            if found {
+                nodes.push(node);
+            }
+        }
+        Ok(nodes)
+    }
+
+    /// The pointers of a new node for the key, to the latest nodes of every other branch.
+    /// Pointers to `deleted` are left out.
+    async fn branches(
+        &self,
+        key: &str,
+        hidden: bool,
+        deleted: Option<u64>,
+    ) -> anyhow::Result<Vec<[Option<u64>; 5]>> {
+        let target = hash(&split(key));
+        let end = target.len() * 4 + 2;
+
+        let mut trie = vec![[None; 5]; end];
+        if let Some(mut head) = self.head().await? {
+            let mut i = 0;
+            while i < end {
+                let value = path(&target, hidden, i);
+                let head_value = head.path(i);
+                let bucket = head.trie.get(i).copied().unwrap_or([None; 5]);
+                for (other, seq) in bucket.iter().enumerate() {
+                    if other as u8 == value && value != TERMINATOR {
+                        continue;
+                    }
+                    if seq.is_some() && *seq != deleted {
+                        trie[i][other] = *seq;
+                    }
+                }
+
+                if head_value == value && (head_value != TERMINATOR || head.key == key) {
+                    i += 1;
+                    continue;
+                }
+                if Some(head.seq) != deleted {
+                    trie[i][head_value as usize] = Some(head.seq);
+                }
+                if value == TERMINATOR {
+                    break;
+                }
+                head = match bucket[value as usize] {
+                    Some(seq) => self.node(seq).await?,
+                    None => break,
+                };
+                i += 1;
+            }
+        }
+        Ok(trie)
+    }
+
+    /// The node a deletion left out, comparing the pointers of the node it appended
+    /// with the ones a put of the same key would have. The trie is the one before `node`.
+    pub async fn deleted_by(&self, node: &Node) -> anyhow::Result<Option<u64>> {
+        let expected = self.branches(&node.key, node.hidden, None).await?;
+        let kept: Vec<u64> = node.trie.iter().flatten().flatten().copied().collect();
+        Ok(expected
+            .iter()
+            .flatten()
+            .flatten()
+            .copied()
+            .find(|seq| !kept.contains(seq)))
+    }
+
+    /// Appends a node for the key, pointing to the latest nodes of every other branch.
+    /// Pointers to `deleted` are left out.
+    async fn put_node(
+        &self,
+        key: &str,
+        value: Option<Vec<u8>>,
+        flags: u64,
+        deleted: Option<u64>,
+    ) -> anyhow::Result<u64> {
+        let key = normalize(key);
+        let trie = self.branches(key, flags & 1 == 1, deleted).await?;
+
+        let mut node = crate::schema::Node::new();
+        node.set_key(key.to_string());
+        if let Some(value) = value {
+            node.set_valueBuffer(value);
+        }
+        node.set_trieBuffer(encode_trie(&trie));
+        node.set_seq(self.feed.read().await.len());
+        node.set_flags(flags);
+        let bytes = protobuf::Message::write_to_bytes(&node)?;
+        self.hub.append(self.feed, &bytes).await
+    }
+
+    /// Callers hold the writer lock of the drive, as the node points to the current head
+    pub async fn put(&self, key: &str, value: Vec<u8>) -> anyhow::Result<u64> {
+        self.put_node(key, Some(value), 0, None).await
+    }
+
+    /// Removes the key by rewriting the node closest to it without the pointer to the key.
+    /// Returns false if the key was not found. Callers hold the writer lock of the drive.
+    pub async fn del(&self, key: &str) -> anyhow::Result<bool> {
+        let mut head = match self.head().await? {
+            Some(head) => head,
+            None => return Ok(false),
+        };
+        let key = normalize(key);
+        let target = hash(&split(key));
+        let end = target.len() * 4 + 2;
+
+        // The node sharing the longest path with the key
+        let mut closest = None;
+        let mut i = 0;
+        while i < end {
+            let value = path(&target, false, i);
+            if head.path(i) == value {
+                let sibling = (0..=TERMINATOR)
+                    .filter(|other| *other != value)
+                    .find_map(|other| head.pointer(i, other));
+                if sibling.is_some() {
+                    closest = sibling;
+                }
+                i += 1;
+                continue;
+            }
+            closest = Some(head.seq);
+            head = match head.pointer(i, value) {
+                Some(seq) => self.node(seq).await?,
+                None => return Ok(false),
+            };
+            i += 1;
+        }
+        if head.key != key || head.value.is_none() {
+            return Ok(false);
+        }
+
+        match closest {
+            Some(seq) => {
+                let closest = self.node(seq).await?;
+                self.put_node(&closest.key, closest.value, closest.flags, Some(head.seq))
+                    .await?;
+            }
+            // Nothing else is left on the trie
+            None => {
+                self.put_node("", None, 0, Some(head.seq)).await?;
+            }
+        }
+        Ok(true)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::hyperdrive::create_in_memory;
+    use async_std::task;
+
+    #[test]
+    fn varints_round_trip() {
+        for value in &[0, 1, 127, 128, 300, u64::from(u32::MAX), u64::MAX] {
+            let mut buf = Vec::new();
+            write_varint(&mut buf, *value);
+            let mut ptr = 0;
+            assert_eq!(read_varint(&buf, &mut ptr).unwrap(), *value);
+            assert_eq!(ptr, buf.len());
+        }
+        assert!(read_varint(&[0x80], &mut 0).is_err());
+        assert!(read_varint(&[0xff; 11], &mut 0).is_err());
+    }
+
+    #[test]
+    fn trie_buffers_round_trip() {
+        let mut trie = vec![[None; 5]; 6];
+        trie[0][1] = Some(3);
+        trie[2][0] = Some(1);
+        trie[2][4] = Some(300);
+        trie[5][2] = Some(7);
+        assert_eq!(decode_trie(&encode_trie(&trie), 6).unwrap(), trie);
+        assert!(decode_trie(&encode_trie(&trie), 5).is_err());
+        assert!(decode_trie(&[], 6).unwrap().is_empty());
+    }
+
+    #[test]
+    fn puts_gets_and_deletes_keys() {
+        task::block_on(async {
+            let drive = create_in_memory(hypercore::generate_keypair())
+                .await
+                .unwrap();
+            let trie = drive.trie();
+            trie.put("/a/b", b"b".to_vec()).await.unwrap();
+            trie.put("/a/c", b"c".to_vec()).await.unwrap();
+            trie.put("/d", b"d".to_vec()).await.unwrap();
+
+            let node = trie.get("a/b").await.unwrap().unwrap();
+            assert_eq!(node.value, Some(b"b".to_vec()));
+            let mut keys: Vec<String> = trie
+                .list("/a")
+                .await
+                .unwrap()
+                .into_iter()
+                .map(|node| node.key)
+                .collect();
+            keys.sort();
+            assert_eq!(keys, vec!["a/b", "a/c"]);
+
+            assert!(trie.del("/a/b").await.unwrap());
+            assert!(!trie.del("/a/b").await.unwrap());
+            assert!(trie.get("/a/b").await.unwrap().is_none());
+            assert!(trie.get("/a/c").await.unwrap().is_some());
+            assert!(trie.get("/d").await.unwrap().is_some());
+        })
+    }
+
+    #[test]
+    fn children_stop_at_the_next_name() {
+        task::block_on(async {
+            let drive = create_in_memory(hypercore::generate_keypair())
+                .await
+                .unwrap();
+            let trie = drive.trie();
+            for key in &["/a/b/c", "/a/b/d", "/a/b/e/f", "/a/g", "/h"] {
+                trie.put(key, key.as_bytes().to_vec()).await.unwrap();
+            }
+
+            // One key for each name
+            let mut names: Vec<String> = trie
+                .children("/a")
+                .await
+                .unwrap()
+                .iter()
+                .map(|node| split(&node.key)[1].to_string())
+                .collect();
+            names.sort();
+            assert_eq!(names, vec!["b", "g"]);
+
+            let first = trie.first("/a/b").await.unwrap().unwrap();
+            assert!(first.key.starts_with("a/b/"));
+            assert!(trie.first("/a/x").await.unwrap().is_none());
+            assert_eq!(trie.children("/").await.unwrap().len(), 2);
+        })
+    }
+}
@@ -5,74 +5,325 @@ use async_std::{
     sync::RwLock,
     task,
 };
-use colmeia_hyperdrive::Hyperdrive;
-use ed25519_dalek::PublicKey;
-use futures::{Future, Stream, StreamExt};
-use hypercore_protocol::ProtocolBuilder;
-use std::{collections::HashSet, net::SocketAddr, sync::Arc, time::Duration};
+use colmeia_hypercore::{FeedOptions, PeerError};
+use colmeia_hyperdrive::{HeaderError, Hyperdrive};
+use colmeia_hyperswarm_mdns::MdnsDiscovery;
 
-pub struct Hyperstack<Storage>
+use crate::peers::{Peer, PeerOrigin, PeerState, PeerTable, Tracker};
+use crate::replicable::Replicable;
+use ed25519_dalek::{Keypair, PublicKey};
+use futures::{
+    channel::{
+        mpsc::{unbounded, UnboundedReceiver, UnboundedSender},
+        oneshot,
+    },
+    future::Shared,
+    io::{AsyncRead, AsyncWrite},
+    stream, Future, Stream, StreamExt,
+};
+use hypercore_protocol::{Protocol, ProtocolBuilder};
+use std::{
+    collections::HashMap,
+    net::{IpAddr, SocketAddr},
+    sync::{Arc, Mutex, PoisonError},
+    time::{Duration, Instant},
+};
+
+/// How long a peer that sent invalid data is ignored
+const BLOCKLIST_DURATION: Duration = Duration::from_secs(60 * 10);
+
+fn live() -> FeedOptions {
+    FeedOptions {
+        live: true,
+        ..FeedOptions::default()
+    }
+}
+
+/// Peers are blocked by IP, as the port changes on every connection
+type Blocklist = Arc<RwLock<HashMap<IpAddr, Instant>>>;
+
+/// Resolves once the topic is left
+type Left = Shared<oneshot::Receiver<()>>;
+
+/// A key joined on the stack
+struct Topic<T> {
+    key: PublicKey,
+    replicable: T,
+    left: Left,
+    // Dropped when leaving, which stops every replication of the topic
+    _leave: oneshot::Sender<()>,
+}
+
+type Discovery = Box<dyn Stream<Item = (Vec<u8>, SocketAddr)> + Unpin + Send>;
+
+/// Peers added by hand, see `Hyperstack::add_peer`
+type ManualPeers = UnboundedReceiver<(Vec<u8>, SocketAddr)>;
+
+/// Joined keys, by discovery key
+type Topics<T> = Arc<Mutex<HashMap<Vec<u8>, Topic<T>>>>;
+
+fn joined<T>(topics: &Topics<T>, discovery_key: &[u8]) -> Option<(T, Left)>
 where
-    Storage: random_access_storage::RandomAccess<Error = Box<dyn std::error::Error + Send + Sync>>
-        + std::fmt::Debug
-        + Send
-        + Sync
-        + 'static,
+    T: Replicable,
 {
-    key: PublicKey,
-    hyperdrive: Arc<RwLock<Hyperdrive<Storage>>>,
-    connected_peers: Arc<RwLock<HashSet<SocketAddr>>>,
+    topics
+        .lock()
+        .unwrap_or_else(PoisonError::into_inner)
+        .get(discovery_key)
+        .map(|topic| (topic.replicable.clone(), topic.left.clone()))
+}
+
+/// Stops replicating the topic and stops announcing it
+async fn leave_topic<T>(
+    topics: &Topics<T>,
+    lan: Option<&MdnsDiscovery>,
+    discovery_key: &[u8],
+) -> anyhow::Result<()> {
+    topics
+        .lock()
+        .unwrap_or_else(PoisonError::into_inner)
+        .remove(discovery_key);
+    if let Some(lan) = lan {
+        lan.remove_topic(discovery_key.to_vec()).await?;
+    }
+    Ok(())
+}
+
+async fn is_blocked(blocklist: &Blocklist, peer: &SocketAddr) -> bool {
+    matches!(blocklist.read().await.get(&peer.ip()), Some(since) if since.elapsed() < BLOCKLIST_DURATION)
+}
+
+/// The discovery key of the first feed the remote opens, to know what it is after
+async fn requested_topic<C>(
+    client: &mut Protocol<C, C>,
+    tracker: &Tracker,
+) -> anyhow::Result<Vec<u8>>
+where
+    C: AsyncRead + AsyncWrite + Send + Unpin + Clone + 'static,
+{
+    loop {
+        match client.loop_next().await? {
+            hypercore_protocol::Event::DiscoveryKey(discovery_key) => return Ok(discovery_key),
+            hypercore_protocol::Event::Handshake(public_key) => {
+                tracker
+                    .remote
+                    .lock()
+                    .unwrap_or_else(PoisonError::into_inner)
+                    .public_key = Some(public_key);
+            }
+            _ => {}
+        }
+    }
+}
+
+/// Replicates the topic with the peer, until either side is done or the topic is left
+async fn replicate_topic<T, C>(
+    replicable: T,
+    left: Left,
+    client: Protocol<C, C>,
+    options: FeedOptions,
+    tracker: &Tracker,
+) -> anyhow::Result<()>
+where
+    T: Replicable,
+    C: AsyncRead + AsyncWrite + Send + Unpin + Clone + 'static,
+{
+    let stopped = async {
+        let _ = left.await;
+        Ok(())
+    };
+    replicable
+        .replicate(client, options, tracker.remote.clone())
+        .race(stopped)
+        .await
+}
+
+async fn on_replication_end(
+    blocklist: &Blocklist,
+    failures: &UnboundedSender<(Vec<u8>, HeaderError)>,
+    peer: SocketAddr,
+    discovery_key: Vec<u8>,
+    result: anyhow::Result<()>,
+) {
+    if let Err(error) = result {
+        if error.downcast_ref::<PeerError>().is_some() {
+            log::warn!("blocking peer {:?}: {}", peer, error);
+            let mut blocklist = blocklist.write().await;
+            blocklist.retain(|_, since| since.elapsed() < BLOCKLIST_DURATION);
+            blocklist.insert(peer.ip(), Instant::now());
+            return;
+        }
+        match error.downcast::<HeaderError>() {
+            Ok(error) => {
+                let _ = failures.unbounded_send((discovery_key, error));
+            }
+            Err(error) => log::debug!("replication with {:?} failed: {:?}", peer, error),
+        }
+    }
+}
+
+/// Finds peers for every key joined and replicates with them,
+/// routing each connection to what the key refers to.
+pub struct Hyperstack<T>
+where
+    T: Replicable,
+{
+    topics: Topics<T>,
+    peers: PeerTable,
+    blocklist: Blocklist,
     listen_address: SocketAddr,
-    discovery: Option<Box<dyn Stream<Item = (Vec<u8>, SocketAddr)> + Unpin + Send>>,
+    options: FeedOptions,
+    lan: Option<MdnsDiscovery>,
+    // Behind a mutex only so the stack can be shared between tasks
+    discovery: Mutex<Option<Discovery>>,
+    manual: UnboundedSender<(Vec<u8>, SocketAddr)>,
+    manual_peers: Mutex<Option<ManualPeers>>,
+    failures: Option<UnboundedSender<(PublicKey, HeaderError)>>,
 }
 
-impl Hyperstack<random_access_disk::RandomAccessDisk> {
-    // pub fn in_disk<P: AsRef<std::path::PathBuf>>(
-    //     key: HashUrl,
-    //     listen_address: SocketAddr,
-    //     metadata: P,
-    //     content: P,
-    // ) -> anyhow::Result<Self> {
-    //     let peers = Arc::new(RwLock::new(SegQueue::new()));
-    //     let hyperdrive = hyperdrive::in_disk(key.public_key().clone(), metadata, content)?;
-    //     Ok(Self {
-    //         key,
-    //         listen_address,
-    //         peers,
-    //         hyperdrive: Arc::new(RwLock::new(hyperdrive)),
-    //         discovery: Box::new(stream::empty()),
-    //     })
-    // }
+type SharedDrive<Storage> = Arc<RwLock<Hyperdrive<Storage>>>;
+
+impl Hyperstack<SharedDrive<random_access_disk::RandomAccessDisk>> {
+    pub async fn in_disk(
+        key: PublicKey,
+        listen_address: SocketAddr,
+        dir: impl AsRef<std::path::Path>,
+    ) -> anyhow::Result<Self> {
+        let hyperdrive = colmeia_hyperdrive::in_disk(key, dir).await?;
+        let hyperstack = Self::new(listen_address);
+        hyperstack
+            .join(key, Arc::new(RwLock::new(hyperdrive)))
+            .await?;
+        Ok(hyperstack)
+    }
+
+    /// Seeds a drive we can write to, kept on the folder
+    pub async fn create_in_disk(
+        keypair: Keypair,
+        listen_address: SocketAddr,
+        dir: impl AsRef<std::path::Path>,
+    ) -> anyhow::Result<Self> {
+        let key = keypair.public;
+        let hyperdrive = colmeia_hyperdrive::create_in_disk(keypair, dir).await?;
+        let hyperstack = Self::new(listen_address);
+        hyperstack
+            .join(key, Arc::new(RwLock::new(hyperdrive)))
+            .await?;
+        Ok(hyperstack)
+    }
 }
 
-impl Hyperstack<random_access_memory::RandomAccessMemory> {
+impl Hyperstack<SharedDrive<random_access_memory::RandomAccessMemory>> {
     pub async fn in_memory(key: PublicKey, listen_address: SocketAddr) -> anyhow::Result<Self> {
         let hyperdrive = colmeia_hyperdrive::in_memmory(key).await?;
-        Ok(Self {
-            key,
-            listen_address,
-            connected_peers: Arc::new(RwLock::new(HashSet::new())),
-            hyperdrive: Arc::new(RwLock::new(hyperdrive)),
-            discovery: None,
-        })
+        let hyperstack = Self::new(listen_address);
+        hyperstack
+            .join(key, Arc::new(RwLock::new(hyperdrive)))
+            .await?;
+        Ok(hyperstack)
     }
 }
 
-// TODO add_topic and remove_topic
-// TODO handle multiple feeds
-impl<Storage> Hyperstack<Storage>
+impl<T> Hyperstack<T>
 where
-    Storage: random_access_storage::RandomAccess<Error = Box<dyn std::error::Error + Send + Sync>>
-        + std::fmt::Debug
-        + Send
-        + Sync,
+    T: Replicable,
 {
-    pub async fn lan(&self) -> anyhow::Result<impl Stream<Item = (Vec<u8>, SocketAddr)>> {
-        let mut mdns = colmeia_hyperswarm_mdns::MdnsDiscovery::new();
+    /// A stack without any key, see `join`
+    pub fn new(listen_address: SocketAddr) -> Self {
+        let (manual, manual_peers) = unbounded();
+        Self {
+            topics: Arc::new(Mutex::new(HashMap::new())),
+            listen_address,
+            peers: PeerTable::default(),
+            blocklist: Arc::new(RwLock::new(HashMap::new())),
+            options: live(),
+            lan: None,
+            discovery: Mutex::new(None),
+            manual,
+            manual_peers: Mutex::new(Some(manual_peers)),
+            failures: None,
+        }
+    }
+
+    /// Starts replicating the key, announcing it on the lan if configured.
+    /// Can be called while replicating.
+    pub async fn join(&self, key: PublicKey, replicable: T) -> anyhow::Result<()> {
+        let discovery_key = hypercore_protocol::discovery_key(key.as_bytes());
+        {
+            let mut topics = self.topics.lock().unwrap_or_else(PoisonError::into_inner);
+            if topics.contains_key(&discovery_key) {
+                return Ok(());
+            }
+            let (leave, left) = oneshot::channel();
+            let topic = Topic {
+                key,
+                replicable,
+                left: futures::FutureExt::shared(left),
+                _leave: leave,
+            };
+            topics.insert(discovery_key.clone(), topic);
+        }
+        if let Some(lan) = &self.lan {
+            if let Err(error) = lan.add_topic(discovery_key.clone()).await {
+                // Not joined after all, so joining again retries the announcement
+                if let Err(undo) = leave_topic(&self.topics, Some(lan), &discovery_key).await {
+                    log::debug!("could not undo the announcement: {:?}", undo);
+                }
+                return Err(error);
+            }
+        }
+        Ok(())
+    }
+
+    /// Stops replicating the key with every peer, and stops announcing it
+    pub async fn leave(&self, key: &PublicKey) -> anyhow::Result<()> {
+        let discovery_key = hypercore_protocol::discovery_key(key.as_bytes());
+        leave_topic(&self.topics, self.lan.as_ref(), &discovery_key).await
+    }
+
+    pub fn get(&self, key: &PublicKey) -> Option<T> {
+        let discovery_key = hypercore_protocol::discovery_key(key.as_bytes());
+        joined(&self.topics, &discovery_key).map(|(replicable, _)| replicable)
+    }
+
+    /// Every key joined
+    pub fn keys(&self) -> Vec<PublicKey> {
+        self.topics
+            .lock()
+            .unwrap_or_else(PoisonError::into_inner)
+            .values()
+            .map(|topic| topic.key)
+            .collect()
+    }
+
+    /// Connects to the peer for the key, as if it was discovered.
+    /// Can be called while replicating.
+    pub fn add_peer(&self, key: &PublicKey, address: SocketAddr) {
+        let discovery_key = hypercore_protocol::discovery_key(key.as_bytes());
+        let _ = self.manual.unbounded_send((discovery_key, address));
+    }
+
+    /// Every peer connected to since replication started, including the ones disconnected
+    pub fn peers(&self) -> Vec<Peer> {
+        self.peers.list()
+    }
+
+    /// Announces and looks up every key joined, now and later, on the local network
+    pub async fn lan(&mut self) -> anyhow::Result<impl Stream<Item = (Vec<u8>, SocketAddr)>> {
+        let mut mdns = MdnsDiscovery::new();
         mdns.with_announcer(self.listen_address.port())
             .with_locator(Duration::from_secs(60));
-        mdns.add_topic(hypercore_protocol::discovery_key(self.key.as_bytes()))
-            .await?;
+        let discovery_keys: Vec<Vec<u8>> = self
+            .topics
+            .lock()
+            .unwrap_or_else(PoisonError::into_inner)
+            .keys()
+            .cloned()
+            .collect();
+        for discovery_key in discovery_keys {
+            mdns.add_topic(discovery_key).await?;
+        }
+        self.lan = Some(mdns.clone());
         Ok(mdns)
     }
 
@@ -80,50 +331,102 @@ where
         &mut self,
         mechanisms: impl Stream<Item = (Vec<u8>, SocketAddr)> + Unpin + 'static + Send,
     ) -> &mut Self {
-        self.discovery = Some(Box::new(mechanisms));
+        *self
+            .discovery
+            .get_mut()
+            .unwrap_or_else(PoisonError::into_inner) = Some(Box::new(mechanisms));
         self
     }
 
-    pub fn hyperdrive(&self) -> Arc<RwLock<Hyperdrive<Storage>>> {
-        self.hyperdrive.clone()
+    /// How the drive feeds are replicated with every peer, live by default
+    pub fn with_options(&mut self, options: FeedOptions) -> &mut Self {
+        self.options = options;
+        self
     }
 
+    /// Keys left because a peer showed they are not a hyperdrive, as no other peer would do better.
+    /// Must be called before `replicate`.
+    pub fn failures(&mut self) -> UnboundedReceiver<(PublicKey, HeaderError)> {
+        let (sender, receiver) = unbounded();
+        self.failures = Some(sender);
+        receiver
+    }
+
+    /// Replicates with every peer found, until stopped
     // TODO Move this method into a HypercoreExt trait?
-    // TODO Allow access to the hypercore, so we can spanw the sync and keep using the hyperdrives
     pub fn replicate(&mut self) -> impl Future<Output = ()> + 'static {
-        let discovery_driver = self.hyperdrive.clone();
-        let discovery_connected_peers = self.connected_peers.clone();
-        let discovery = self.discovery.take();
+        let (failed, mut failures) = unbounded();
+        let topics = self.topics.clone();
+        let lan = self.lan.clone();
+        let reported = self.failures.take();
+
+        let discovery_topics = self.topics.clone();
+        let discovery_peers = self.peers.clone();
+        let discovery_blocklist = self.blocklist.clone();
+        let discovery_failed = failed.clone();
+        let discovered = stream::iter(
+            self.discovery
+                .get_mut()
+                .unwrap_or_else(PoisonError::into_inner)
+                .take(),
+        )
+        .flatten()
+        .map(|(discovery_key, peer)| (discovery_key, peer, PeerOrigin::Discovered));
+        let manual = stream::iter(
+            self.manual_peers
+                .get_mut()
+                .unwrap_or_else(PoisonError::into_inner)
+                .take(),
+        )
+        .flatten()
+        .map(|(discovery_key, peer)| (discovery_key, peer, PeerOrigin::Manual));
+        let mut found = stream::select(discovered, manual);
 
         let listen_address = self.listen_address;
-        let listen_driver = self.hyperdrive.clone();
-        let listen_connected_peers = self.connected_peers.clone();
+        let options = self.options;
+        let listen_topics = self.topics.clone();
+        let listen_peers = self.peers.clone();
+        let listen_blocklist = self.blocklist.clone();
+        let listen_failed = failed;
 
         async move {
-            let discovery = match discovery {
-                Some(mut discovery) => task::spawn(async move {
-                    while let Some((_, peer)) = discovery.next().await {
-                        if !discovery_connected_peers.read().await.contains(&peer) {
-                            let driver = discovery_driver.clone();
-                            let connected_peers = discovery_connected_peers.clone();
+            let discovery = task::spawn(async move {
+                while let Some((discovery_key, peer, origin)) = found.next().await {
+                    if is_blocked(&discovery_blocklist, &peer).await {
+                        continue;
+                    }
+                    let (replicable, left) = match joined(&discovery_topics, &discovery_key) {
+                        Some(topic) => topic,
+                        None => continue,
+                    };
+                    let tracker = Tracker::default();
+                    let connection = match discovery_peers.track(
+                        peer,
+                        discovery_key.clone(),
+                        origin,
+                        PeerState::Connecting,
+                        tracker.clone(),
+                    ) {
+                        Some(connection) => connection,
+                        None => continue,
+                    };
+                    let blocklist = discovery_blocklist.clone();
+                    let failed = discovery_failed.clone();
 
-                            task::spawn(async move {
-                                if let Ok(tcp_stream) = TcpStream::connect(peer).await {
-                                    connected_peers.write().await.insert(peer);
-                                    let client = ProtocolBuilder::new(true).connect(tcp_stream);
-                                    colmeia_hyperdrive::replicate_hyperdrive(
-                                        client,
-                                        driver.clone(),
-                                    )
-                                    .await;
-                                    connected_peers.write().await.remove(&peer);
-                                }
-                            });
+                    task::spawn(async move {
+                        if let Ok(tcp_stream) = TcpStream::connect(peer).await {
+                            connection.replicating();
+                            let client =
+                                ProtocolBuilder::new(true).connect(tracker.stream(tcp_stream));
+                            let result =
+                                replicate_topic(replicable, left, client, options, &tracker).await;
+                            drop(connection);
+                            on_replication_end(&blocklist, &failed, peer, discovery_key, result)
+                                .await;
                         }
-                    }
-                }),
-                None => task::spawn(async {}),
-            };
+                    });
+                }
+            });
 
             let listening = task::spawn(async move {
                 let listener = TcpListener::bind(listen_address)
@@ -133,21 +436,60 @@ where
                 if let Ok(listener) = listener {
                     loop {
                         if let Ok((tcp_stream, remote_addrs)) = listener.accept().await {
-                            let driver = listen_driver.clone();
-                            let connected_peers = listen_connected_peers.clone();
-                            if !connected_peers.read().await.contains(&remote_addrs) {
-                                task::spawn(async move {
-                                    log::debug!("Received connection from {:?}", remote_addrs);
-                                    connected_peers.write().await.insert(remote_addrs);
-                                    let client = ProtocolBuilder::new(false).connect(tcp_stream);
-                                    colmeia_hyperdrive::replicate_hyperdrive(
-                                        client,
-                                        driver.clone(),
-                                    )
-                                    .await;
-                                    connected_peers.write().await.remove(&remote_addrs);
-                                });
+                            if is_blocked(&listen_blocklist, &remote_addrs).await {
+                                continue;
                             }
+                            let topics = listen_topics.clone();
+                            let peers = listen_peers.clone();
+                            let blocklist = listen_blocklist.clone();
+                            let failed = listen_failed.clone();
+                            task::spawn(async move {
+                                log::debug!("Received connection from {:?}", remote_addrs);
+                                let tracker = Tracker::default();
+                                let mut client =
+                                    ProtocolBuilder::new(false).connect(tracker.stream(tcp_stream));
+                                let discovery_key = match requested_topic(&mut client, &tracker)
+                                    .await
+                                {
+                                    Ok(discovery_key) => discovery_key,
+                                    Err(error) => {
+                                        log::debug!("{:?} left early: {:?}", remote_addrs, error);
+                                        return;
+                                    }
+                                };
+                                let (replicable, left) = match joined(&topics, &discovery_key) {
+                                    Some(topic) => topic,
+                                    None => {
+                                        log::debug!(
+                                            "{:?} asked for a key not joined",
+                                            remote_addrs
+                                        );
+                                        return;
+                                    }
+                                };
+                                let connection = match peers.track(
+                                    remote_addrs,
+                                    discovery_key.clone(),
+                                    PeerOrigin::Incoming,
+                                    PeerState::Replicating,
+                                    tracker.clone(),
+                                ) {
+                                    Some(connection) => connection,
+                                    None => return,
+                                };
+                                let result =
+                                    replicate_topic(replicable, left, client, options, &tracker)
+                                        .await;
+                                drop(connection);
+                                on_replication_end(
+                                    &blocklist,
+                                    &failed,
+                                    remote_addrs,
+                                    discovery_key,
+                                    result,
+                                )
+                                .await;
+                            });
                         }
                     }
                 } else {
@@ -155,7 +497,23 @@ where
                 }
             });
 
-            listening.join(discovery).await;
+            let leaving = async {
+                while let Some((discovery_key, error)) = failures.next().await {
+                    log::error!("leaving {}: {}", hex::encode(&discovery_key), error);
+                    let key = topics
+                        .lock()
+                        .unwrap_or_else(PoisonError::into_inner)
+                        .get(&discovery_key)
+                        .map(|topic| topic.key);
+                    if let Err(error) = leave_topic(&topics, lan.as_ref(), &discovery_key).await {
+                        log::warn!("could not stop announcing: {:?}", error);
+                    }
+                    if let (Some(key), Some(reported)) = (key, &reported) {
+                        let _ = reported.unbounded_send((key, error));
+                    }
+                }
+            };
+            listening.join(discovery).join(leaving).await;
         }
     }
 }
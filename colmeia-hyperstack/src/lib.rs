@@ -1,5 +1,10 @@
 mod hyperstack;
+mod peers;
+mod replicable;
 pub mod utils;
 
+pub use colmeia_hypercore as hypercore;
 pub use colmeia_hyperdrive as hyperdrive;
 pub use hyperstack::*;
+pub use peers::{Peer, PeerOrigin, PeerState};
+pub use replicable::{Hypercore, Replicable};
@@ -0,0 +1,299 @@
+use async_std::net::TcpStream;
+use colmeia_hypercore::RemotePeer;
+use futures::io::{AsyncRead, AsyncWrite};
+use std::{
+    collections::HashMap,
+    io,
+    net::SocketAddr,
+    pin::Pin,
+    sync::{
+        atomic::{AtomicU64, Ordering},
+        Arc, Mutex, PoisonError,
+    },
+    task::{Context, Poll},
+    time::{Duration, Instant},
+};
+
+/// How long a disconnected peer stays listed
+const PEER_RETENTION: Duration = Duration::from_secs(10 * 60);
+
+/// How a peer was found
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum PeerOrigin {
+    /// Found by the discovery mechanisms, like mDNS
+    Discovered,
+    /// Connected to us
+    Incoming,
+    /// Added with `Hyperstack::add_peer`
+    Manual,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum PeerState {
+    Connecting,
+    Replicating,
+    /// The connection ended, or could not be made
+    Disconnected,
+}
+
+/// A peer we connected to for one of the keys joined, see `Hyperstack::peers`
+#[derive(Debug, Clone)]
+pub struct Peer {
+    pub address: SocketAddr,
+    /// The discovery key of the topic replicated
+    pub discovery_key: Vec<u8>,
+    pub origin: PeerOrigin,
+    pub state: PeerState,
+    /// The remote public key and the feeds shared with it
+    pub remote: RemotePeer,
+    pub bytes_sent: u64,
+    pub bytes_received: u64,
+    /// When the peer last sent us anything, or when it was found
+    pub last_seen: Instant,
+}
+
+#[derive(Debug)]
+struct Traffic {
+    sent: AtomicU64,
+    received: AtomicU64,
+    last_seen: Mutex<Instant>,
+}
+
+impl Default for Traffic {
+    fn default() -> Self {
+        Self {
+            sent: AtomicU64::new(0),
+            received: AtomicU64::new(0),
+            last_seen: Mutex::new(Instant::now()),
+        }
+    }
+}
+
+/// What is learned about a peer while connected to it
+#[derive(Debug, Clone, Default)]
+pub(crate) struct Tracker {
+    pub remote: Arc<Mutex<RemotePeer>>,
+    traffic: Arc<Traffic>,
+}
+
+impl Tracker {
+    /// Wraps the connection, counting what goes through it
+    pub fn stream(&self, stream: TcpStream) -> MeteredStream {
+        MeteredStream {
+            stream,
+            traffic: self.traffic.clone(),
+        }
+    }
+}
+
+#[derive(Debug, Clone)]
+pub(crate) struct MeteredStream {
+    stream: TcpStream,
+    traffic: Arc<Traffic>,
+}
+
+impl AsyncRead for MeteredStream {
+    fn poll_read(
+        mut self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        buf: &mut [u8],
+    ) -> Poll<io::Result<usize>> {
+        let poll = Pin::new(&mut self.stream).poll_read(cx, buf);
+        if let Poll::Ready(Ok(read)) = poll {
+            if read > 0 {
+                self.traffic
+                    .received
+                    .fetch_add(read as u64, Ordering::Relaxed);
+                *self
+                    .traffic
+                    .last_seen
+                    .lock()
+                    .unwrap_or_else(PoisonError::into_inner) = Instant::now();
+            }
+        }
+        poll
+    }
+}
+
+impl AsyncWrite for MeteredStream {
+    fn poll_write(
+        mut self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        buf: &[u8],
+    ) -> Poll<io::Result<usize>> {
+        let poll = Pin::new(&mut self.stream).poll_write(cx, buf);
+        if let Poll::Ready(Ok(written)) = poll {
+            self.traffic
+                .sent
+                .fetch_add(written as u64, Ordering::Relaxed);
+        }
+        poll
+    }
+
+    fn poll_flush(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+        Pin::new(&mut self.stream).poll_flush(cx)
+    }
+
+    fn poll_close(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+        Pin::new(&mut self.stream).poll_close(cx)
+    }
+}
+
+struct Entry {
+    origin: PeerOrigin,
+    state: PeerState,
+    tracker: Tracker,
+    /// When the connection ended
+    disconnected: Option<Instant>,
+}
+
+/// A peer address and the discovery key replicated with it
+type Key = (SocketAddr, Vec<u8>);
+
+/// Every peer connected to, by address and topic
+#[derive(Clone, Default)]
+pub(crate) struct PeerTable {
+    peers: Arc<Mutex<HashMap<Key, Entry>>>,
+}
+
+impl PeerTable {
+    /// Starts tracking the connection, unless the peer is already connected for the topic
+    pub fn track(
+        &self,
+        address: SocketAddr,
+        discovery_key: Vec<u8>,
+        origin: PeerOrigin,
+        state: PeerState,
+        tracker: Tracker,
+    ) -> Option<Connection> {
+        let key = (address, discovery_key);
+        let mut peers = self.peers.lock().unwrap_or_else(PoisonError::into_inner);
+        forget_disconnected(&mut peers, PEER_RETENTION);
+        if matches!(peers.get(&key), Some(entry) if entry.state != PeerState::Disconnected) {
+            return None;
+        }
+        let entry = Entry {
+            origin,
+            state,
+            tracker,
+            disconnected: None,
+        };
+        peers.insert(key.clone(), entry);
+        Some(Connection {
+            table: self.clone(),
+            key,
+        })
+    }
+
+    fn set_state(&self, key: &Key, state: PeerState) {
+        if let Some(entry) = self
+            .peers
+            .lock()
+            .unwrap_or_else(PoisonError::into_inner)
+            .get_mut(key)
+        {
+            entry.state = state;
+            if state == PeerState::Disconnected {
+                entry.disconnected = Some(Instant::now());
+            }
+        }
+    }
+
+    pub fn list(&self) -> Vec<Peer> {
+        let mut peers = self.peers.lock().unwrap_or_else(PoisonError::into_inner);
+        forget_disconnected(&mut peers, PEER_RETENTION);
+        peers
+            .iter()
+            .map(|((address, discovery_key), entry)| {
+                let traffic = &entry.tracker.traffic;
+                Peer {
+                    address: *address,
+                    discovery_key: discovery_key.clone(),
+                    origin: entry.origin,
+                    state: entry.state,
+                    remote: entry
+                        .tracker
+                        .remote
+                        .lock()
+                        .unwrap_or_else(PoisonError::into_inner)
+                        .clone(),
+                    bytes_sent: traffic.sent.load(Ordering::Relaxed),
+                    bytes_received: traffic.received.load(Ordering::Relaxed),
+                    last_seen: *traffic
+                        .last_seen
+                        .lock()
+                        .unwrap_or_else(PoisonError::into_inner),
+                }
+            })
+            .collect()
+    }
+}
+
+/// Removes the peers disconnected for longer than the retention
+fn forget_disconnected(peers: &mut HashMap<Key, Entry>, retention: Duration) {
+    peers.retain(
+        |_, entry| !matches!(entry.disconnected, Some(since) if since.elapsed() >= retention),
+    );
+}
+
+/// A tracked connection, marked as disconnected once dropped
+pub(crate) struct Connection {
+    table: PeerTable,
+    key: Key,
+}
+
+impl Connection {
+    pub fn replicating(&self) {
+        self.table.set_state(&self.key, PeerState::Replicating);
+    }
+}
+
+impl Drop for Connection {
+    fn drop(&mut self) {
+        self.table.set_state(&self.key, PeerState::Disconnected);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn track(table: &PeerTable, port: u16) -> Option<Connection> {
+        table.track(
+            SocketAddr::from(([127, 0, 0, 1], port)),
+            vec![0; 32],
+            PeerOrigin::Manual,
+            PeerState::Connecting,
+            Tracker::default(),
+        )
+    }
+
+    #[test]
+    fn connected_peers_are_tracked_once() {
+        let table = PeerTable::default();
+        let connection = track(&table, 3282).expect("tracked");
+        assert!(track(&table, 3282).is_none());
+        connection.replicating();
+        assert_eq!(table.list()[0].state, PeerState::Replicating);
+        drop(connection);
+        assert_eq!(table.list()[0].state, PeerState::Disconnected);
+        assert!(track(&table, 3282).is_some());
+    }
+
+    #[test]
+    fn disconnected_peers_are_forgotten_after_the_retention() {
+        let table = PeerTable::default();
+        let connected = track(&table, 3282).expect("tracked");
+        drop(track(&table, 3283).expect("tracked"));
+        let mut peers = table.peers.lock().unwrap_or_else(PoisonError::into_inner);
+        forget_disconnected(&mut peers, PEER_RETENTION);
+        assert_eq!(peers.len(), 2);
+        forget_disconnected(&mut peers, Duration::from_secs(0));
+        assert_eq!(peers.len(), 1);
+        assert!(peers
+            .values()
+            .all(|entry| entry.state == PeerState::Connecting));
+        drop(peers);
+        drop(connected);
+    }
+}
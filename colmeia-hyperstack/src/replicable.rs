@@ -0,0 +1,160 @@
+use anyhow::Context;
+use async_std::sync::RwLock;
+use colmeia_hypercore::{FeedHub, FeedMultiplexer, FeedOptions, RemotePeer};
+use colmeia_hyperdrive::{DiskStorage, FeedStorage, Hyperdrive, MemoryStorage};
+use futures::io::{AsyncRead, AsyncWrite};
+use hypercore_protocol::Protocol;
+use std::{
+    path::Path,
+    sync::{Arc, Mutex},
+};
+
+/// What a `Hyperstack` finds peers for and replicates with each of them.
+/// What the peer tells us while replicating is kept on `remote`.
+#[async_trait::async_trait]
+pub trait Replicable: Clone + Send + Sync + 'static {
+    async fn replicate<C>(
+        &self,
+        client: Protocol<C, C>,
+        options: FeedOptions,
+        remote: Arc<Mutex<RemotePeer>>,
+    ) -> anyhow::Result<()>
+    where
+        C: AsyncRead + AsyncWrite + Send + Unpin + Clone + 'static;
+}
+
+#[async_trait::async_trait]
+impl<Storage> Replicable for Arc<RwLock<Hyperdrive<Storage>>>
+where
+    Storage: random_access_storage::RandomAccess<Error = Box<dyn std::error::Error + Send + Sync>>
+        + std::fmt::Debug
+        + Send
+        + Sync
+        + 'static,
+{
+    async fn replicate<C>(
+        &self,
+        client: Protocol<C, C>,
+        options: FeedOptions,
+        remote: Arc<Mutex<RemotePeer>>,
+    ) -> anyhow::Result<()>
+    where
+        C: AsyncRead + AsyncWrite + Send + Unpin + Clone + 'static,
+    {
+        colmeia_hyperdrive::replicate_hyperdrive_tracked(client, self.clone(), options, remote)
+            .await
+    }
+}
+
+/// A single append-only log, without any of the hyperdrive structure.
+/// Append through the hub, so peers replicating it are told about new blocks.
+pub struct Hypercore<Storage>
+where
+    Storage: random_access_storage::RandomAccess<Error = Box<dyn std::error::Error + Send + Sync>>
+        + std::fmt::Debug
+        + Send
+        + Sync,
+{
+    pub feed: Arc<RwLock<hypercore::Feed<Storage>>>,
+    pub hub: FeedHub,
+}
+
+impl<Storage> Clone for Hypercore<Storage>
+where
+    Storage: random_access_storage::RandomAccess<Error = Box<dyn std::error::Error + Send + Sync>>
+        + std::fmt::Debug
+        + Send
+        + Sync,
+{
+    fn clone(&self) -> Self {
+        Self {
+            feed: self.feed.clone(),
+            hub: self.hub.clone(),
+        }
+    }
+}
+
+impl<Storage> Hypercore<Storage>
+where
+    Storage: random_access_storage::RandomAccess<Error = Box<dyn std::error::Error + Send + Sync>>
+        + std::fmt::Debug
+        + Send
+        + Sync,
+{
+    pub fn new(feed: hypercore::Feed<Storage>) -> Self {
+        Self {
+            feed: Arc::new(RwLock::new(feed)),
+            hub: FeedHub::new(),
+        }
+    }
+
+    async fn open(
+        public_key: hypercore::PublicKey,
+        secret_key: Option<hypercore::SecretKey>,
+        storage: &dyn FeedStorage<Storage>,
+    ) -> anyhow::Result<Self> {
+        let mut builder = hypercore::Feed::builder(public_key, storage.open(&public_key).await?);
+        if let Some(secret_key) = secret_key {
+            builder = builder.secret_key(secret_key);
+        }
+        let feed = builder.build().await.context("Could not start feed")?;
+        Ok(Self::new(feed))
+    }
+
+    /// Appends a block, returning its index
+    pub async fn append(&self, data: &[u8]) -> anyhow::Result<u64> {
+        self.hub.append(&self.feed, data).await
+    }
+}
+
+impl Hypercore<random_access_disk::RandomAccessDisk> {
+    /// Keeps the feed on a folder, laid out the same way drives are
+    pub async fn in_disk(
+        public_key: hypercore::PublicKey,
+        dir: impl AsRef<Path>,
+    ) -> anyhow::Result<Self> {
+        Self::open(public_key, None, &DiskStorage::new(dir)).await
+    }
+
+    /// A feed we can append to, kept on a folder
+    pub async fn create_in_disk(
+        keypair: ed25519_dalek::Keypair,
+        dir: impl AsRef<Path>,
+    ) -> anyhow::Result<Self> {
+        Self::open(keypair.public, Some(keypair.secret), &DiskStorage::new(dir)).await
+    }
+}
+
+impl Hypercore<random_access_memory::RandomAccessMemory> {
+    pub async fn in_memory(public_key: hypercore::PublicKey) -> anyhow::Result<Self> {
+        Self::open(public_key, None, &MemoryStorage).await
+    }
+}
+
+#[async_trait::async_trait]
+impl<Storage> Replicable for Hypercore<Storage>
+where
+    Storage: random_access_storage::RandomAccess<Error = Box<dyn std::error::Error + Send + Sync>>
+        + std::fmt::Debug
+        + Send
+        + Sync
+        + 'static,
+{
+    async fn replicate<C>(
+        &self,
+        client: Protocol<C, C>,
+        options: FeedOptions,
+        remote: Arc<Mutex<RemotePeer>>,
+    ) -> anyhow::Result<()>
+    where
+        C: AsyncRead + AsyncWrite + Send + Unpin + Clone + 'static,
+    {
+        let multiplexer = FeedMultiplexer::new();
+        multiplexer
+            .add(self.feed.clone(), self.hub.clone(), options)
+            .await;
+        multiplexer
+            .replicate_tracked(client, futures::sink::drain(), remote)
+            .await
+    }
+}
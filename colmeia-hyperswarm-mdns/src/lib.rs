@@ -55,6 +55,7 @@ pub fn self_id() -> String {
     format!("id={}", hex::encode(generated_id))
 }
 
+#[derive(Clone)]
 pub struct MdnsDiscovery {
     self_id: String,
     announce: Arc<RwLock<Option<announcer::Announcer>>>,
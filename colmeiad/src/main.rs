@@ -1,14 +1,24 @@
 use async_std::{sync::RwLock, task};
-use colmeia_hyperstack::{hyperdrive::Hyperdrive, utils::PublicKeyExt, Hyperstack};
-use futures::future::OptionFuture;
-use std::sync::Arc;
+use colmeia_hyperstack::{
+    hyperdrive::{Change, Hyperdrive},
+    utils::PublicKeyExt,
+    Hyperstack, Peer, PeerOrigin, PeerState,
+};
+use futures::{future::OptionFuture, StreamExt, TryStreamExt};
+use std::{collections::HashMap, path::PathBuf, sync::Arc};
 use tide::{Request, StatusCode};
 
-fn name() -> String {
+/// Where the drives are kept, the first argument. The keys to join on start follow it.
+fn folder() -> PathBuf {
     let args: Vec<String> = std::env::args().skip(1).collect();
     args.first()
-        .expect("must have hash name as argument")
-        .into()
+        .map(PathBuf::from)
+        .unwrap_or_else(|| PathBuf::from(".colmeia"))
+}
+
+/// Drives to join on start, more can be joined later
+fn keys() -> Vec<String> {
+    std::env::args().skip(2).collect()
 }
 
 #[derive(serde::Serialize, Debug)]
@@ -19,32 +29,236 @@ struct FeedInfo {
 
 #[derive(serde::Serialize, Debug)]
 struct Info {
+    version: u64,
     metadata: FeedInfo,
     content: Option<FeedInfo>,
 }
 
-type State<Storage> = Arc<RwLock<Hyperdrive<Storage>>>;
+type Drive = Arc<RwLock<Hyperdrive<random_access_disk::RandomAccessDisk>>>;
 
-async fn get_info<Storage>(req: Request<State<Storage>>) -> tide::Result<tide::Response>
-where
-    Storage: random_access_storage::RandomAccess<Error = Box<dyn std::error::Error + Send + Sync>>
-        + std::fmt::Debug
-        + Send
-        + Sync,
-{
-    let driver = req.state().read().await;
+/// Every drive is kept on the same folder.
+/// The hyperstack only locks its drives to look them up or add them, requests don't wait on each other.
+#[derive(Clone)]
+struct State {
+    hyperstack: Arc<Hyperstack<Drive>>,
+    folder: PathBuf,
+}
+
+fn key(req: &Request<State>) -> tide::Result<hypercore::PublicKey> {
+    let key: String = req
+        .param("key")
+        .map_err(|e| tide::Error::from_str(StatusCode::BadRequest, e))?;
+    key.parse_from_hash()
+        .map_err(|e| tide::Error::from_str(StatusCode::BadRequest, e))
+}
 
-    let content: OptionFuture<_> = driver.content.as_ref().map(feed_info).into();
+async fn drive(req: &Request<State>) -> tide::Result<Drive> {
+    let key = key(req)?;
+    req.state()
+        .hyperstack
+        .get(&key)
+        .ok_or_else(|| tide::Error::from_str(StatusCode::NotFound, "drive not joined"))
+}
+
+async fn list_drives(req: Request<State>) -> tide::Result<tide::Response> {
+    let keys: Vec<String> = req
+        .state()
+        .hyperstack
+        .keys()
+        .iter()
+        .map(hex::encode)
+        .collect();
+    Ok(tide::Response::builder(200)
+        .body(tide::convert::json!(keys))
+        .build())
+}
+
+/// Starts replicating the drive, downloading it to the folder
+async fn join_drive(req: Request<State>) -> tide::Result<tide::Response> {
+    let key = key(&req)?;
+    let state = req.state();
+    if state.hyperstack.get(&key).is_some() {
+        return Ok(tide::Response::new(StatusCode::Ok));
+    }
+
+    // Opening reads the feeds from disk, while other drives keep being served
+    let hyperdrive = colmeia_hyperstack::hyperdrive::in_disk(key, &state.folder).await?;
+    state
+        .hyperstack
+        .join(key, Arc::new(RwLock::new(hyperdrive)))
+        .await?;
+    Ok(tide::Response::new(StatusCode::Created))
+}
+
+/// Stops replicating the drive, keeping what was downloaded
+async fn leave_drive(req: Request<State>) -> tide::Result<tide::Response> {
+    let key = key(&req)?;
+    req.state().hyperstack.leave(&key).await?;
+    Ok(tide::Response::new(StatusCode::NoContent))
+}
+
+async fn get_info(req: Request<State>) -> tide::Result<tide::Response> {
+    let driver = drive(&req).await?;
+    let driver = driver.read().await;
+
+    let content: OptionFuture<_> = driver.content().map(feed_info).into();
     let content = content.await.transpose()?;
 
     let metadata = feed_info(&driver.metadata).await?;
 
-    let info = Info { metadata, content };
+    let version = driver.version().await;
+
+    let info = Info {
+        version,
+        metadata,
+        content,
+    };
     Ok(tide::Response::builder(200)
         .body(tide::convert::json!(info))
         .build())
 }
 
+#[derive(serde::Serialize, Debug)]
+struct ChangeInfo {
+    #[serde(rename = "type")]
+    kind: &'static str,
+    name: String,
+}
+
+impl From<Change> for ChangeInfo {
+    fn from(change: Change) -> Self {
+        match change {
+            Change::Put { name, .. } => ChangeInfo { kind: "put", name },
+            Change::Del { name, .. } => ChangeInfo { kind: "del", name },
+        }
+    }
+}
+
+#[derive(serde::Serialize, Debug)]
+struct Diff {
+    version: u64,
+    changes: Vec<ChangeInfo>,
+}
+
+fn from_version(req: &Request<State>) -> tide::Result<u64> {
+    req.param("from")
+        .map_err(|e| tide::Error::from_str(StatusCode::BadRequest, e))
+}
+
+/// Every change since the `from` version, up to the current one
+async fn diff_since(driver: &Drive, from: u64) -> tide::Result<tide::Response> {
+    let driver = driver.read().await;
+    let version = driver.version().await;
+    let changes: Vec<Change> = driver.diff(from, version).await?.try_collect().await?;
+    let changes = changes.into_iter().map(ChangeInfo::from).collect();
+
+    let diff = Diff { version, changes };
+    Ok(tide::Response::builder(200)
+        .body(tide::convert::json!(diff))
+        .build())
+}
+
+/// Files changed since the given version, to show what a sync brought
+async fn get_diff(req: Request<State>) -> tide::Result<tide::Response> {
+    let from = from_version(&req)?;
+    let driver = drive(&req).await?;
+    diff_since(&driver, from).await
+}
+
+/// Waits until the drive changes after the `from` version, then returns every change since it
+/// with the new version, so clients can refresh without polling or missing changes
+async fn watch_changes(req: Request<State>) -> tide::Result<tide::Response> {
+    let from = from_version(&req)?;
+    let driver = drive(&req).await?;
+    // Watching before checking the version, so nothing written in between is missed
+    let changes = driver.read().await.watch("/").await;
+    if driver.read().await.version().await <= from {
+        futures::pin_mut!(changes);
+        changes.next().await.transpose()?;
+    }
+    diff_since(&driver, from).await
+}
+
+#[derive(serde::Serialize, Debug)]
+struct PeerInfo {
+    address: String,
+    discovery_key: String,
+    origin: &'static str,
+    state: &'static str,
+    public_key: Option<String>,
+    /// Remote length of each feed shared, by discovery key
+    feeds: HashMap<String, u64>,
+    bytes_sent: u64,
+    bytes_received: u64,
+    /// Seconds since the peer last sent anything
+    last_seen: u64,
+}
+
+impl From<Peer> for PeerInfo {
+    fn from(peer: Peer) -> Self {
+        let origin = match peer.origin {
+            PeerOrigin::Discovered => "discovered",
+            PeerOrigin::Incoming => "incoming",
+            PeerOrigin::Manual => "manual",
+        };
+        let state = match peer.state {
+            PeerState::Connecting => "connecting",
+            PeerState::Replicating => "replicating",
+            PeerState::Disconnected => "disconnected",
+        };
+        PeerInfo {
+            address: peer.address.to_string(),
+            discovery_key: hex::encode(&peer.discovery_key),
+            origin,
+            state,
+            public_key: peer.remote.public_key.map(hex::encode),
+            feeds: peer
+                .remote
+                .feeds
+                .into_iter()
+                .map(|(discovery_key, length)| (hex::encode(discovery_key), length))
+                .collect(),
+            bytes_sent: peer.bytes_sent,
+            bytes_received: peer.bytes_received,
+            last_seen: peer.last_seen.elapsed().as_secs(),
+        }
+    }
+}
+
+/// Every peer connected to, to see how replication is going
+async fn list_peers(req: Request<State>) -> tide::Result<tide::Response> {
+    let peers: Vec<PeerInfo> = req
+        .state()
+        .hyperstack
+        .peers()
+        .into_iter()
+        .map(PeerInfo::from)
+        .collect();
+    Ok(tide::Response::builder(200)
+        .body(tide::convert::json!(peers))
+        .build())
+}
+
+#[derive(serde::Deserialize, Debug)]
+struct NewPeer {
+    address: std::net::SocketAddr,
+}
+
+/// Connects to a peer for the drive, for peers not found on the local network
+async fn add_peer(mut req: Request<State>) -> tide::Result<tide::Response> {
+    let NewPeer { address } = req.body_json().await?;
+    let key = key(&req)?;
+    let hyperstack = &req.state().hyperstack;
+    if hyperstack.get(&key).is_none() {
+        return Err(tide::Error::from_str(
+            StatusCode::NotFound,
+            "drive not joined",
+        ));
+    }
+    hyperstack.add_peer(&key, address);
+    Ok(tide::Response::new(StatusCode::Accepted))
+}
+
 async fn feed_info<Storage>(feed: &Arc<RwLock<hypercore::Feed<Storage>>>) -> tide::Result<FeedInfo>
 where
     Storage: random_access_storage::RandomAccess<Error = Box<dyn std::error::Error + Send + Sync>>
@@ -67,24 +281,41 @@ where
 async fn main() -> Result<(), std::io::Error> {
     env_logger::from_env(env_logger::Env::default().default_filter_or("info")).init();
 
-    let key = name();
-    let hash = key.parse_from_hash().expect("invalid hash argument");
-
-    let mut hyperstack = Hyperstack::in_memory(hash, "0.0.0.0:3899".parse().unwrap())
-        .await
-        .expect("Could not start hyperdrive on the stack");
+    let folder = folder();
+    let mut hyperstack = Hyperstack::new("0.0.0.0:3899".parse().unwrap());
+    for key in keys() {
+        let hash = key.parse_from_hash().expect("invalid hash argument");
+        let hyperdrive = colmeia_hyperstack::hyperdrive::in_disk(hash, &folder)
+            .await
+            .expect("Could not open hyperdrive");
+        hyperstack
+            .join(hash, Arc::new(RwLock::new(hyperdrive)))
+            .await
+            .expect("could not join the hyperdrive");
+    }
     let mdns = hyperstack
         .lan()
         .await
-        .expect("could not add key to mdns discovery");
+        .expect("could not configure mdns discovery");
     hyperstack.with_discovery(mdns);
 
     let job = task::spawn(hyperstack.replicate());
-    let hyperdrive = hyperstack.hyperdrive();
+    let state = State {
+        hyperstack: Arc::new(hyperstack),
+        folder,
+    };
 
-    let mut app = tide::with_state(hyperdrive);
+    let mut app = tide::with_state(state);
     app.middleware(tide::log::LogMiddleware::new());
-    app.at("/").get(get_info);
+    app.at("/").get(list_drives);
+    app.at("/:key")
+        .get(get_info)
+        .put(join_drive)
+        .delete(leave_drive);
+    app.at("/peers").get(list_peers);
+    app.at("/:key/peers").post(add_peer);
+    app.at("/:key/diff/:from").get(get_diff);
+    app.at("/:key/watch/:from").get(watch_changes);
     app.listen("127.0.0.1:8080").await?;
     job.await;
     Ok(())